@@ -4,9 +4,11 @@
 //! Main CLI entry point for Plainsync application.
 
 use plainsync::commands;
+use plainsync::commands::run::RunOptions;
+use plainsync::core::task::TaskSelector;
 use plainsync::context::ExecutionContext;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use anyhow::Result;
 use tracing_subscriber::EnvFilter;
 
@@ -26,13 +28,78 @@ pub enum Commands {
 
         #[arg(short, long)]
         verbose: bool,
+
+        /// Override the configured bandwidth limit (0 = unlimited)
+        #[arg(long, value_name = "BYTES")]
+        max_bytes_per_sec: Option<u64>,
+
+        /// Show planned uploads and deletes without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Sync in this process even when a daemon is running
+        #[arg(long)]
+        local: bool,
+
+        #[command(flatten)]
+        select: SelectArgs,
     },
 
+    /// Show devices, last sync and pending changes
+    Status,
+
     /// View or edit configuration
     Config(ConfigCommands),
 
     /// Inspect and manage index files
     Index(IndexCommands),
+
+    /// Control the running daemon
+    Daemon(DaemonCommands),
+
+    /// Manage the cache of transcoded files
+    Cache(CacheCommands),
+}
+
+/// Selectors narrowing the tasks of a run (repeatable or comma-separated)
+#[derive(Args, Debug, Default)]
+pub struct SelectArgs {
+    /// Only sync these devices
+    #[arg(long = "device", value_name = "NAME", value_delimiter = ',')]
+    devices: Vec<String>,
+
+    /// Only sync folders with these targets
+    #[arg(long = "folder", value_name = "TARGET", value_delimiter = ',')]
+    folders: Vec<String>,
+
+    /// Only sync folders with these tags
+    #[arg(long = "tag", value_name = "TAG", value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Skip these devices
+    #[arg(long = "exclude-device", value_name = "NAME", value_delimiter = ',')]
+    exclude_devices: Vec<String>,
+
+    /// Skip folders with these targets
+    #[arg(long = "exclude-folder", value_name = "TARGET", value_delimiter = ',')]
+    exclude_folders: Vec<String>,
+
+    /// Skip folders with these tags
+    #[arg(long = "exclude-tag", value_name = "TAG", value_delimiter = ',')]
+    exclude_tags: Vec<String>,
+}
+
+impl From<SelectArgs> for TaskSelector {
+    fn from(args: SelectArgs) -> Self {
+        Self {
+            devices: args.devices,
+            folders: args.folders,
+            tags: args.tags,
+            exclude_devices: args.exclude_devices,
+            exclude_folders: args.exclude_folders,
+            exclude_tags: args.exclude_tags,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -90,6 +157,62 @@ pub enum IndexSubcommands {
     },
 }
 
+#[derive(Parser)]
+pub struct CacheCommands {
+    #[command(subcommand)]
+    pub command: CacheSubcommands,
+}
+
+#[derive(Subcommand)]
+pub enum CacheSubcommands {
+    /// Remove transcoded files, they are produced again when needed
+    Clean {
+        /// Only remove files added to the cache more than DAYS ago
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+    },
+}
+
+#[derive(Parser)]
+pub struct DaemonCommands {
+    #[command(subcommand)]
+    pub command: DaemonSubcommands,
+}
+
+#[derive(Subcommand)]
+pub enum DaemonSubcommands {
+    /// Show active syncs, progress and last results
+    Status,
+
+    /// Sync now the mounted folders of a device, or one folder
+    SyncNow {
+        /// Only sync this device
+        #[arg(long, value_name = "NAME")]
+        device: Option<String>,
+
+        /// Only sync the folder with this target
+        #[arg(long, value_name = "TARGET")]
+        folder: Option<String>,
+
+        /// Wait for the syncs to finish and print their results
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// Stop syncing on changes until resumed
+    Pause,
+
+    /// Sync the changes made while paused and follow new ones
+    Resume,
+
+    /// Print daemon events as they happen
+    WatchEvents {
+        /// Print raw JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
@@ -102,8 +225,18 @@ fn main() -> Result<()> {
     let ctx = ExecutionContext::from_default_config()?;
 
     match cli.command {
-        Commands::Run { verbose, no_progress_bar } =>
-            commands::run::run_sync(&ctx, verbose, no_progress_bar)?,
+        Commands::Run { verbose, no_progress_bar, max_bytes_per_sec, dry_run, local, select } =>
+            commands::run::run_sync(&ctx, &RunOptions {
+                verbose,
+                no_progress: no_progress_bar,
+                max_bytes_per_sec,
+                dry_run,
+                selector: select.into(),
+                local,
+            })?,
+
+        Commands::Status =>
+            commands::status::run_status(&ctx)?,
 
         Commands::Config(cmd) => match cmd.command {
             ConfigSubcommands::Show =>
@@ -126,6 +259,28 @@ fn main() -> Result<()> {
             IndexSubcommands::Stats { device } =>
                 commands::index::run_stats(&ctx, device.as_deref())?,
         },
+
+        Commands::Daemon(cmd) => match cmd.command {
+            DaemonSubcommands::Status =>
+                commands::daemon::run_status()?,
+
+            DaemonSubcommands::SyncNow { device, folder, wait } =>
+                commands::daemon::run_sync_now(device.as_deref(), folder.as_deref(), wait)?,
+
+            DaemonSubcommands::Pause =>
+                commands::daemon::run_pause()?,
+
+            DaemonSubcommands::Resume =>
+                commands::daemon::run_resume()?,
+
+            DaemonSubcommands::WatchEvents { json } =>
+                commands::daemon::run_watch_events(json)?,
+        },
+
+        Commands::Cache(cmd) => match cmd.command {
+            CacheSubcommands::Clean { older_than } =>
+                commands::cache::run_clean(&ctx, older_than)?,
+        },
     }
 
     Ok(())
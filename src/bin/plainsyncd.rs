@@ -6,11 +6,18 @@
 use anyhow::Result;
 use tracing_subscriber::EnvFilter;
 
+use plainsync::config::AppConfig;
+use plainsync::daemon::control::start_control_socket;
+use plainsync::daemon::reload::watch_config;
+use plainsync::daemon::signals::{start_signal_handler, Signal};
 use plainsync::daemon::state::DaemonState;
+use plainsync::daemon::watchdog::start_watchdog;
 use plainsync::sync::detect_loop::{start_detect_loop, DetectCallbacks};
 use plainsync::sync::engine::SyncEngine;
 use plainsync::sync::watcher::start_watcher;
-use plainsync::context::ExecutionContext;
+use plainsync::context::{ExecutionContext, SharedContext};
+use plainsync::utils::paths::control_socket;
+use plainsync::utils::throttle::lower_priority;
 
 // À mettre au niveau module, pas dans main()
 static DAEMON_STATE: once_cell::sync::OnceCell<DaemonState> = once_cell::sync::OnceCell::new();
@@ -21,9 +28,32 @@ fn main() -> Result<()> {
     .init();
     tracing::info!("Plainsync daemon starting…");
 
-    // 1. Build execution context
+    let config_path = AppConfig::filepath()?;
+
+    // 0. Signals, before any other thread starts: SIGHUP reloads the configuration,
+    //    SIGTERM and SIGINT stop the daemon
+    let reload_path = config_path.clone();
+    let (stop_tx, stop_rx) = std::sync::mpsc::channel();
+    start_signal_handler(&[Signal::SIGHUP, Signal::SIGTERM, Signal::SIGINT], move |signal| {
+        if signal != Signal::SIGHUP {
+            tracing::info!("Received {}, stopping.", signal);
+            let _ = stop_tx.send(());
+            return;
+        }
+        let Some(state) = DAEMON_STATE.get() else { return };
+        tracing::info!("Received {}, reloading configuration.", signal);
+        // Errors are reported by reload_config
+        let _ = state.reload_config(&reload_path);
+    })?;
+
+    // 1. Build execution context, shared with detect_loop and swapped on reload
     let ctx = ExecutionContext::from_default_config()?;
-    let ctx_for_detect = ctx.clone(); // clone for detect_loop
+    let shared = SharedContext::new(ctx.clone());
+
+    // Lower priority before spawning threads so that every sync inherits it
+    if ctx.config.io.low_priority {
+        lower_priority();
+    }
 
     // 2. Create engine
     let engine = SyncEngine::new();
@@ -38,14 +68,23 @@ fn main() -> Result<()> {
         },
     )?;
 
-    // 4. Build daemon state (ctx is MOVED here)
+    let watcher_heartbeat = watcher.heartbeat();
+
+    // 4. Build daemon state
     DAEMON_STATE
-        .set(DaemonState::new(ctx, engine, watcher))
+        .set(DaemonState::new(shared.clone(), engine, watcher))
         .unwrap();
 
-    // 5. Hot-plug detection (uses the CLONE of ctx)
-    start_detect_loop(
-        ctx_for_detect,
+    // 5. Control socket for `plainsync daemon` and other clients
+    let socket = control_socket();
+    start_control_socket(&socket, DAEMON_STATE.get().unwrap().clone())?;
+
+    // 6. Reload the configuration when config.yaml changes
+    let config_watcher = watch_config(DAEMON_STATE.get().unwrap().clone(), config_path)?;
+
+    // 7. Hot-plug detection (follows the shared context)
+    let detect = start_detect_loop(
+        shared,
         DetectCallbacks {
             on_mount: move |dev| {
                 let state = DAEMON_STATE.get().unwrap();
@@ -63,7 +102,27 @@ fn main() -> Result<()> {
     // Notify systemd that we're ready
     sd_notify::notify(false, &[sd_notify::NotifyState::Ready]).ok();
 
-    std::thread::park();
+    // Keep STATUS= current and send watchdog keepalives while every loop is alive
+    let watchdog = start_watchdog(
+        DAEMON_STATE.get().unwrap().clone(),
+        vec![
+            ("watcher", watcher_heartbeat),
+            ("config watcher", config_watcher.heartbeat()),
+            ("detect", detect.heartbeat()),
+        ],
+    );
+
+    // 8. Run until SIGTERM/SIGINT, then stop without losing work:
+    //    the sync in progress finishes its current file and checkpoints its index
+    let _ = stop_rx.recv();
+    sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]).ok();
+
+    DAEMON_STATE.get().unwrap().shutdown();
+    let _ = watchdog.join();
+    config_watcher.stop();
+    detect.stop();
+    let _ = std::fs::remove_file(&socket);
 
+    tracing::info!("Daemon stopped.");
     Ok(())
 }
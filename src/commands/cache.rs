@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT
+// src/commands/cache.rs
+
+//! Commands for the host cache of transcoded outputs.
+
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::context::ExecutionContext;
+use crate::sync::transcode::clean_cache;
+use crate::utils::human::human_size;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Command to remove transcoded outputs, all of them or those older than `older_than_days`.
+pub fn run_clean(ctx: &ExecutionContext, older_than_days: Option<u64>) -> Result<()> {
+    let cache = ctx.config.transcode_cache()?;
+    let cache = ctx.expander.expand(&cache.to_string_lossy(), "");
+
+    let older_than = older_than_days.map(|days| Duration::from_secs(days * SECS_PER_DAY));
+    let (count, bytes) = clean_cache(&cache, older_than)?;
+
+    println!("Removed {} cached file(s) ({}) from {}.", count, human_size(bytes), cache.display());
+    Ok(())
+}
@@ -17,6 +17,10 @@ pub fn run_edit() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn limit(value: Option<usize>) -> String {
+    value.map_or_else(|| "none".to_string(), |v| v.to_string())
+}
+
 // Show the current sync configuration.
 pub fn run_show(ctx: &ExecutionContext) -> Result<()> {
     let tasks = ctx.config.load_tasks()?;
@@ -32,8 +36,28 @@ pub fn run_show(ctx: &ExecutionContext) -> Result<()> {
         
         println!("\nDevice:");
         println!("  Name: {}", dev);
-        println!("  Mount path: {}", ctx.expand_mount(&dev).to_str().unwrap_or(""));
+        println!("  Mount path: {}", ctx.expand_mount(dev).to_str().unwrap_or(""));
         println!("  Use mountinfo: {}", dev.mountinfo);
+        if let Some(profile) = &dev.profile {
+            println!("  Profile: {}", profile);
+        }
+        let fs = &dev.filesystem;
+        println!(
+            "  Filesystem: portable_names={} max_name={} max_path={} max_depth={}",
+            fs.portable_names,
+            limit(fs.max_name),
+            limit(fs.max_path),
+            limit(fs.max_depth)
+        );
+        match &dev.formats {
+            Some(formats) if dev.formats_from_profile => println!(
+                "  Formats: {} (from the {} profile, synced files in other formats are kept)",
+                formats.join(", "),
+                dev.profile.as_deref().unwrap_or_default()
+            ),
+            Some(formats) => println!("  Formats: {}", formats.join(", ")),
+            None => println!("  Formats: any"),
+        }
 
 
         // Now find matching SyncTasks for this device
@@ -52,6 +76,47 @@ pub fn run_show(ctx: &ExecutionContext) -> Result<()> {
             println!("      Target: {}", task.target.display());
             println!("      Index : {}", task.index_path.display());
             println!("      Enabled : {}", task.folder.enabled);
+            println!("      Mode : {:?}", task.mode);
+            println!("      Symlinks : {:?}", task.folder.symlinks);
+            if let Some(template) = &task.folder.template {
+                println!("      Template : {}", template);
+            }
+            for rule in &task.folder.transcode {
+                println!("      Transcode : {} -> {} ({})", rule.from, rule.to, rule.command);
+            }
+            if let Some(layout) = &task.folder.layout {
+                println!("      Layout : {}", layout);
+            }
+            for rule in &task.folder.rewrite {
+                println!("      Rewrite : {} -> {}", rule.from, rule.to);
+            }
+            if let Some(depth) = task.folder.max_depth {
+                println!("      Max depth : {}", depth);
+            }
+            if let Some(library) = &task.folder.library {
+                println!("      Library : {}", library);
+            }
+            if let Some(p) = &task.folder.playlists {
+                println!(
+                    "      Playlists : albums={} top_level={} all_tracks={} ({:?}, {:?}, separator {:?})",
+                    p.albums, p.top_level, p.all_tracks, p.paths, p.encoding, p.separator
+                );
+            }
+            for (kind, rules) in [
+                ("Include", &task.folder.tag_rules.include),
+                ("Exclude", &task.folder.tag_rules.exclude),
+            ] {
+                for rule in rules {
+                    let conditions: Vec<String> = rule
+                        .iter()
+                        .map(|(k, c)| format!("{}={}", k, c.0.join("|")))
+                        .collect();
+                    println!("      {} : {}", kind, conditions.join(", "));
+                }
+            }
+            if !task.folder.tags.is_empty() {
+                println!("      Tags : {}", task.folder.tags.join(", "));
+            }
         }
     }
 
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: MIT
+// src/commands/daemon.rs
+
+//! Commands talking to a running daemon through its control socket.
+
+use anyhow::Result;
+use comfy_table::{Table, presets::ASCII_FULL};
+
+use crate::daemon::client::DaemonClient;
+use crate::daemon::protocol::{Event, SyncResult};
+use crate::utils::human::{human_date, human_size};
+
+/// Command to show what the daemon is doing.
+pub fn run_status() -> Result<()> {
+    let status = DaemonClient::connect()?.status()?;
+
+    println!("Daemon: {}", if status.paused { "paused" } else { "running" });
+    if status.deferred > 0 {
+        println!("  {} sync(s) waiting for resume", status.deferred);
+    }
+
+    match &status.running {
+        Some(p) => println!(
+            "  Syncing {} / {}: {}/{} operations (since {})",
+            p.device, p.folder, p.done, p.total, human_date(p.started)
+        ),
+        None => println!("  Idle"),
+    }
+
+    if status.active.is_empty() {
+        println!("\nNo active sync (no configured device mounted).");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(ASCII_FULL)
+        .set_header(["Device", "Folder", "Target", "Last sync", "Result"]);
+
+    for active in &status.active {
+        let last = status.results.iter().find(|r| r.device == active.device && r.folder == active.folder);
+        let (when, outcome) = match last {
+            Some(r) => (human_date(r.finished), describe(r)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        table.add_row([active.device.clone(), active.folder.clone(), active.target.clone(), when, outcome]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+/// Command to ask the daemon for an immediate sync.
+pub fn run_sync_now(device: Option<&str>, folder: Option<&str>, wait: bool) -> Result<()> {
+    let mut client = DaemonClient::connect()?;
+
+    if !wait {
+        let count = client.sync(device, folder, None)?;
+        println!("Daemon started {} sync(s).", count);
+        return Ok(());
+    }
+
+    report(&client.sync_wait(device, folder, None)?)
+}
+
+/// Command to pause automatic syncs.
+pub fn run_pause() -> Result<()> {
+    DaemonClient::connect()?.pause()?;
+    println!("Daemon paused, changes are kept until resume.");
+    Ok(())
+}
+
+/// Command to resume automatic syncs.
+pub fn run_resume() -> Result<()> {
+    DaemonClient::connect()?.resume()?;
+    println!("Daemon resumed.");
+    Ok(())
+}
+
+/// Command to print daemon events as they happen, as text or JSON lines.
+pub fn run_watch_events(json: bool) -> Result<()> {
+    for event in DaemonClient::connect()?.subscribe()? {
+        let event = event?;
+        if json {
+            println!("{}", serde_json::to_string(&event)?);
+            continue;
+        }
+
+        let now = chrono::Local::now().format("%H:%M:%S");
+        let line = match &event {
+            Event::DeviceMounted { device } => format!("{} mounted", device),
+            Event::DeviceUnmounted { device } => format!("{} unmounted", device),
+            Event::SyncStarted { device, folder } => format!("Sync started: {} / {}", device, folder),
+            Event::SyncFinished(r) => format!("Sync finished: {} / {}: {}", r.device, r.folder, describe(r)),
+            Event::Paused => "Daemon paused".to_string(),
+            Event::Resumed => "Daemon resumed".to_string(),
+            Event::ConfigReloaded => "Configuration reloaded".to_string(),
+            Event::ConfigError { message } => format!("Configuration error (previous one kept): {}", message),
+        };
+        println!("[{}] {}", now, line);
+    }
+
+    println!("Daemon closed the connection.");
+    Ok(())
+}
+
+/// Print the results of syncs run by the daemon; fails if one of them failed.
+pub fn report(results: &[SyncResult]) -> Result<()> {
+    for r in results {
+        println!("{} / {}: {}", r.device, r.folder, describe(r));
+    }
+
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if failed > 0 {
+        anyhow::bail!("{} sync(s) failed in the daemon", failed);
+    }
+    Ok(())
+}
+
+/// One-line outcome of a sync
+fn describe(r: &SyncResult) -> String {
+    match &r.error {
+        Some(e) => format!("error: {}", e),
+        None => format!(
+            "{} uploaded ({}), {} deleted ({})",
+            r.uploaded,
+            human_size(r.upload_bytes),
+            r.deleted,
+            human_size(r.delete_bytes)
+        ),
+    }
+}
@@ -5,4 +5,7 @@
 
 pub mod run;
 pub mod index;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod status;
+pub mod daemon;
+pub mod cache;
\ No newline at end of file
@@ -4,13 +4,38 @@
 //! Command to run sync immediately for all configured folders.
 
 use anyhow::Result;
-use crate::core::task::SyncTask;
+use std::time::Duration;
+
+use crate::commands::daemon::report;
+use crate::core::task::{SyncTask, SyncTaskFilter, TaskSelector};
 use crate::context::ExecutionContext;
-use crate::sync::run::sync_folder;
+use crate::daemon::client::DaemonClient;
+use crate::sync::run::{preview_folder, sync_folder};
 use crate::sync::planner::plan_sync;
+use crate::utils::human::SyncStats;
+
+/// Command-line options of `plainsync run`.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub verbose: bool,
+    pub no_progress: bool,
+    /// Overrides the configured bandwidth limit (0 = unlimited)
+    pub max_bytes_per_sec: Option<u64>,
+    /// Only report planned operations, write nothing
+    pub dry_run: bool,
+    /// Restrict the run to some devices, folders or tags
+    pub selector: TaskSelector,
+    /// Sync in this process even when a daemon is running
+    pub local: bool,
+}
+
+/// How long to wait for a running daemon to answer before syncing locally
+const DAEMON_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Run sync immediately for all devices defined in the config.
-pub fn run_sync(ctx: &ExecutionContext, verbose: bool, no_progress: bool) -> Result<()> {
+/// When a daemon is running, it performs the syncs it knows so that both never write
+/// at once; the other folders are synced here.
+pub fn run_sync(ctx: &ExecutionContext, opts: &RunOptions) -> Result<()> {
     tracing::info!("Starting Plainsync run");
 
     let plan = plan_sync(ctx)?;
@@ -20,10 +45,84 @@ pub fn run_sync(ctx: &ExecutionContext, verbose: bool, no_progress: bool) -> Res
         return Ok(());
     }
 
-    for conf in plan {
-        sync_one(&conf, verbose, !no_progress)?;
+    let plan = plan.filter_selected(&opts.selector);
+
+    if plan.is_empty() {
+        println!("No sync task matches the given selection.");
+        return Ok(());
+    }
+
+    if opts.dry_run {
+        return preview_all(&plan);
+    }
+
+    let mut plan = plan;
+    if let Some(mut client) = DaemonClient::probe(DAEMON_PROBE_TIMEOUT).filter(|_| !opts.local) {
+        let status = client.status()?;
+        if status.paused {
+            anyhow::bail!(
+                "The daemon is paused: resume it with `plainsync daemon resume`, or pass --local to sync in this process"
+            );
+        }
+
+        // The daemon only knows the folders of its loaded config and mounted devices
+        let (delegated, local): (Vec<SyncTask>, Vec<SyncTask>) = plan.into_iter().partition(|t| {
+            status.active.iter().any(|a| a.device == t.device.name && a.folder == t.folder.target)
+        });
+        if !delegated.is_empty() {
+            delegate(client, &delegated, opts)?;
+        }
+        if local.is_empty() {
+            return Ok(());
+        }
+        println!("The daemon does not sync {} selected folder(s), syncing them here.", local.len());
+        plan = local;
+    }
+
+    for mut conf in plan {
+        if let Some(limit) = opts.max_bytes_per_sec {
+            conf.max_bytes_per_sec = Some(limit);
+        }
+        sync_one(&conf, opts.verbose, !opts.no_progress)?;
+    }
+
+    Ok(())
+}
+
+/// Have the running daemon sync every task of the plan
+fn delegate(mut client: DaemonClient, plan: &[SyncTask], opts: &RunOptions) -> Result<()> {
+    println!("A daemon is running, it performs the sync.");
+    if opts.verbose {
+        tracing::warn!("--verbose only applies to local syncs, the daemon logs to its own output (pass --local to sync here)");
+    }
+
+    let mut results = Vec::new();
+    for task in plan {
+        if !opts.no_progress {
+            println!("Syncing {} / {}…", task.device, task.folder.target);
+        }
+        results.extend(client.sync_wait(Some(&task.device.name), Some(&task.folder.target), opts.max_bytes_per_sec)?);
+    }
+
+    report(&results)
+}
+
+/// Print planned operations of every task, without writing anything
+fn preview_all(plan: &[SyncTask]) -> Result<()> {
+    let mut total = SyncStats::default();
+
+    for task in plan {
+        println!(
+            "\n[dry-run] {}: {} → {}",
+            task.device,
+            task.source.display(),
+            task.target.display(),
+        );
+        total.merge(&preview_folder(task)?);
     }
 
+    println!("\nDry run total: {}", total.format_summary());
+    println!("No changes were made.");
     Ok(())
 }
 
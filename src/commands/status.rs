@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MIT
+// src/commands/status.rs
+
+//! Read-only overview of configured devices and their pending changes.
+
+use anyhow::Result;
+use comfy_table::{Table, presets::ASCII_FULL};
+use std::collections::HashSet;
+
+use crate::config::SyncMode;
+use crate::context::ExecutionContext;
+use crate::core::journal::Journal;
+use crate::core::task::{SyncTask, SyncTaskFilter, SyncTaskExpand};
+use crate::sync::detect::detect_all_devices;
+use crate::sync::formats::summarize;
+use crate::sync::run::pending_diff;
+use crate::utils::human::{human_date, human_size};
+
+/// Command to show every configured device with its pending changes.
+pub fn run_status(ctx: &ExecutionContext) -> Result<()> {
+    let devices = &ctx.config.devices;
+
+    if devices.is_empty() {
+        println!("No devices configured.");
+        return Ok(());
+    }
+
+    let mounted: HashSet<String> = detect_all_devices(ctx)
+        .into_iter()
+        .map(|(dev, _)| dev.name)
+        .collect();
+
+    let tasks: Vec<SyncTask> = ctx.config.load_tasks()?.expanded(ctx);
+
+    for dev in devices {
+        let is_mounted = mounted.contains(&dev.name);
+
+        println!("\nDevice: {}", dev);
+        println!(
+            "  {}: {}",
+            if is_mounted { "Mounted" } else { "Not mounted" },
+            ctx.expand_mount(dev).display()
+        );
+
+        let dev_tasks = tasks.clone().filter_by_device(&dev.name);
+        if dev_tasks.is_empty() {
+            println!("  (No sync folders for this device)");
+            continue;
+        }
+
+        let mut table = Table::new();
+        table
+            .load_preset(ASCII_FULL)
+            .set_header(["Folder", "Mode", "Last sync", "Uploads", "Deletes", "Unsupported"]);
+
+        for task in dev_tasks {
+            table.add_row(status_row(&task, is_mounted)?);
+        }
+
+        println!("{table}");
+    }
+
+    println!();
+    Ok(())
+}
+
+/// Build one table row describing a folder and its pending changes:
+/// folder, mode, last sync, uploads, deletes, unsupported files.
+pub fn status_row(task: &SyncTask, is_mounted: bool) -> Result<[String; 6]> {
+    let idx = task.load_index()?;
+    let mut last_sync = if idx.exists() {
+        human_date(idx.generated_at)
+    } else {
+        "never".to_string()
+    };
+
+    let resumed = Journal::for_index(&task.index_path).operations()?;
+    if resumed > 0 {
+        last_sync = format!("{} (interrupted, {} operation(s) done)", last_sync, resumed);
+    }
+
+    let mode = format!("{:?}", task.mode);
+
+    if !task.folder.enabled {
+        return Ok([task.folder.target.clone(), mode, last_sync, "disabled".into(), "-".into(), "-".into()]);
+    }
+
+    // Pull sources live on the device, nothing to scan while it is away
+    if !is_mounted && matches!(task.mode, SyncMode::Pull) {
+        return Ok([task.folder.target.clone(), mode, last_sync, "unavailable".into(), "-".into(), "-".into()]);
+    }
+
+    let (uploads, deletes, unsupported) = match pending_diff(task) {
+        Ok((diff, stats)) => (
+            format!("{} (+{})", stats.upload_count, human_size(stats.upload_bytes)),
+            format!("{} (-{})", stats.delete_count, human_size(stats.delete_bytes)),
+            match diff.unsupported.len() {
+                0 => "0".to_string(),
+                n => format!("{} ({})", n, summarize(&diff.unsupported)),
+            },
+        ),
+        Err(e) => {
+            tracing::warn!("Cannot scan {}: {}", task.source.display(), e);
+            ("unavailable".into(), "-".into(), "-".into())
+        }
+    };
+
+    Ok([task.folder.target.clone(), mode, last_sync, uploads, deletes, unsupported])
+}
@@ -15,6 +15,12 @@ use crate::core::task::SyncTask;
 
 pub const DEFAULT_CONFIG: &str = include_str!("../assets/default_config.yaml");
 
+/// Destination layout of imported files, relative to the folder target
+pub const DEFAULT_IMPORT_TEMPLATE: &str = "{year}/{month}/{day}/{name}";
+
+/// Destination layout of moved files: mirror the device layout
+pub const DEFAULT_MOVE_TEMPLATE: &str = "{dir}/{name}";
+
 /// Watcher configuration
 #[derive(Debug, Deserialize, Clone)]
 pub struct WatchConfig {
@@ -22,10 +28,271 @@ pub struct WatchConfig {
     pub debounce_ms: u64,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+/// I/O limits applied to copies
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct IoConfig {
+    /// Global copy bandwidth limit, unless the device sets its own
+    pub max_bytes_per_sec: Option<u64>,
+    /// Lower CPU and I/O priority of daemon-initiated syncs
+    #[serde(default)]
+    pub low_priority: bool,
+}
+
+/// Source metadata carried over to copied files.
+/// Defaults suit FAT/exFAT targets, which only keep timestamps.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PreserveConfig {
+    pub mtime: bool,
+    pub atime: bool,
+    pub permissions: bool,
+    pub xattrs: bool,
+}
+
+impl Default for PreserveConfig {
+    fn default() -> Self {
+        Self { mtime: true, atime: true, permissions: false, xattrs: false }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
 pub enum SyncMode {
     Push,
     Pull,
+    Import,
+    Move,
+    /// Host playlists rewritten to the device paths of a `library` folder
+    Playlists,
+}
+
+/// How symbolic links found in a source folder are handled.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Descend into linked directories and copy linked files
+    Follow,
+    /// Ignore every symlink
+    #[default]
+    Skip,
+    /// Copy the content of linked files, without descending into linked directories
+    CopyTarget,
+}
+
+/// Conversion applied to uploads with a given extension, e.g. flac -> opus.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct TranscodeRule {
+    pub from: String,
+    pub to: String,
+    /// External command, `{input}` and `{output}` are replaced by file paths
+    pub command: String,
+}
+
+impl TranscodeRule {
+    /// Whether the rule applies to a source path (extension, case-insensitive)
+    pub fn matches(&self, path: &str) -> bool {
+        Path::new(path)
+            .extension()
+            .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case(&self.from))
+    }
+
+    /// Target path of a transcoded source file
+    pub fn output_path(&self, path: &str) -> String {
+        Path::new(path).with_extension(&self.to).to_string_lossy().into_owned()
+    }
+
+    /// Settings recorded in the index: any change re-transcodes the outputs
+    pub fn settings(&self) -> String {
+        format!("{} -> {}: {}", self.from, self.to, self.command)
+    }
+}
+
+/// Rewrite of target paths matching the `from` regex into the `to` replacement
+/// (`$name`, `${name}` or `$1` for capture groups), e.g. flattening CD folders.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct RewriteRule {
+    pub from: RewritePattern,
+    pub to: String,
+}
+
+/// Regex matching a whole relative target path
+#[derive(Debug, Clone)]
+pub struct RewritePattern(pub regex::Regex);
+
+impl RewritePattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+            .with_context(|| format!("Invalid rewrite pattern {:?}", pattern))?;
+        Ok(Self(regex))
+    }
+
+    /// The pattern as written in the configuration
+    pub fn as_str(&self) -> &str {
+        let anchored = self.0.as_str();
+        &anchored[4..anchored.len() - 2]
+    }
+}
+
+impl PartialEq for RewritePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for RewritePattern {}
+
+impl fmt::Display for RewritePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RewritePattern {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        RewritePattern::new(&pattern).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// Naming rules and path limits of the device filesystem
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct FilesystemRules {
+    /// Replace characters FAT/exFAT reject and trailing dots or spaces
+    pub portable_names: bool,
+    /// Longest file or directory name, in bytes; longer names are shortened
+    pub max_name: Option<usize>,
+    /// Longest path from the device root, in bytes; longer paths stop the sync
+    pub max_path: Option<usize>,
+    /// Default `max_depth` of the device folders
+    pub max_depth: Option<usize>,
+}
+
+/// Transcoding settings shared by every device
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TranscodeConfig {
+    /// Cache of transcoded outputs, default ~/.cache/plainsync/transcode
+    pub cache: Option<String>,
+}
+
+/// Values a tag must match, any of them: text (case-insensitive, `*` wildcard),
+/// comparisons (`>=4`, `<2000`) or inclusive ranges (`1990..1999`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagCondition(pub Vec<String>);
+
+impl<'de> Deserialize<'de> for TagCondition {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        use serde_yaml::Value;
+
+        fn scalar(v: &Value) -> Option<String> {
+            match v {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            }
+        }
+
+        let value = Value::deserialize(deserializer)?;
+        let values = match &value {
+            Value::Sequence(seq) => seq.iter().map(scalar).collect(),
+            other => scalar(other).map(|s| vec![s]),
+        };
+
+        values
+            .map(TagCondition)
+            .ok_or_else(|| serde::de::Error::custom("expected a tag value or a list of values"))
+    }
+}
+
+/// A tag rule matches a file when every listed tag matches its condition
+pub type TagRule = std::collections::BTreeMap<String, TagCondition>;
+
+/// Selection of tracks by embedded tags
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TagRules {
+    /// When set, a track must match at least one of these rules
+    pub include: Vec<TagRule>,
+    /// A track matching any of these rules is left out
+    pub exclude: Vec<TagRule>,
+}
+
+impl TagRules {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// How playlist entries point to tracks
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistPaths {
+    /// Relative to the playlist file
+    #[default]
+    Relative,
+    /// From the device root, e.g. /Music/Album/01.mp3
+    Absolute,
+}
+
+/// Text encoding of playlist files
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaylistEncoding {
+    /// `.m3u8` files
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// `.m3u` files, characters outside Latin-1 become `?`
+    #[serde(rename = "latin1")]
+    Latin1,
+}
+
+/// Playlists written on the device after a sync
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PlaylistConfig {
+    /// One playlist per directory holding tracks
+    pub albums: bool,
+    /// One playlist per top-level directory, at the folder root
+    pub top_level: bool,
+    /// A playlist of every track, at the folder root
+    pub all_tracks: bool,
+    pub paths: PlaylistPaths,
+    pub separator: String,
+    pub encoding: PlaylistEncoding,
+    /// Extensions of files listed as tracks
+    pub extensions: Vec<String>,
+}
+
+impl Default for PlaylistConfig {
+    fn default() -> Self {
+        Self {
+            albums: true,
+            top_level: false,
+            all_tracks: false,
+            paths: PlaylistPaths::default(),
+            separator: "/".into(),
+            encoding: PlaylistEncoding::default(),
+            extensions: ["mp3", "flac", "ogg", "opus", "m4a", "aac", "wav", "wma", "aiff", "ape"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Shell commands run at lifecycle events of a device or folder
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_mount: Option<String>,
+    pub pre_sync: Option<String>,
+    pub post_sync: Option<String>,
+    pub on_error: Option<String>,
+    pub on_unmount: Option<String>,
 }
 
 /// Device entry in YAML
@@ -33,25 +300,60 @@ pub enum SyncMode {
 pub struct DeviceConfig {
     pub name: String,
     pub label: Option<String>,
+    pub profile: Option<String>, // Bundled preset merged under this device, see `profiles`
     pub mount: String,
     pub mountinfo: bool,
+    pub max_bytes_per_sec: Option<u64>, // None = use global io limit
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub filesystem: FilesystemRules,
+    pub formats: Option<Vec<String>>, // Extensions the device can read, None = any
+    /// `formats` comes from the profile, not from the device entry
+    #[serde(skip)]
+    pub formats_from_profile: bool,
     pub folders: Vec<FolderConfig>,
 }
 
 /// Folder (source to target) entry in YAML
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct FolderConfig {
     pub source: String,
     pub target: String,
     pub enabled: bool,
     pub mode: Option<SyncMode>, // None = push (backward compatible)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub preserve: PreserveConfig,
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    #[serde(default)]
+    pub transcode: Vec<TranscodeRule>,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub tag_rules: TagRules,
+    pub playlists: Option<PlaylistConfig>, // None = no generated playlists
+    pub library: Option<String>, // Playlists: target of the folder holding the tracks
+    pub layout: Option<String>, // Push: target path template from tags, None = mirror the source
+    #[serde(default)]
+    pub rewrite: Vec<RewriteRule>,
+    pub max_depth: Option<usize>, // Push: directories allowed above a file, None = unlimited
+    pub template: Option<String>, // Import/Move destination, None = mode default
+    pub delete_after_days: Option<u32>, // Move: keep originals until this old, None = delete at once
 }
 
 /// Top-level configuration
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
+    #[serde(deserialize_with = "crate::profiles::deserialize_devices")]
     pub devices: Vec<DeviceConfig>,
     pub watch: WatchConfig,
+    #[serde(default)]
+    pub io: IoConfig,
+    #[serde(default)]
+    pub transcode: TranscodeConfig,
 }
 
 impl AppConfig {
@@ -81,7 +383,12 @@ impl AppConfig {
             Self::bootstrap(&path)?;
         }
 
-        let txt = fs::read_to_string(&path)
+        Self::load_from(&path)
+    }
+
+    /// Load an existing configuration file
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let txt = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config: {}", path.display()))?;
 
         let conf: Self = serde_yaml::from_str(&txt)
@@ -90,11 +397,20 @@ impl AppConfig {
         Ok(conf)
     }
 
+    /// Directory of the transcode cache, before expansion
+    pub fn transcode_cache(&self) -> Result<PathBuf> {
+        Ok(match &self.transcode.cache {
+            Some(dir) => PathBuf::from(dir),
+            None => paths::app_cache_dir()?.join("transcode"),
+        })
+    }
+
     /// Build list of active sync configurations
     pub fn load_tasks(&self) -> Result<Vec<SyncTask>> {
 
         let mut out = Vec::new();
         let data_base = paths::app_data_dir()?;
+        let transcode_cache = self.transcode_cache()?;
 
         for device in &self.devices {
             for folder in &device.folders {
@@ -107,13 +423,13 @@ impl AppConfig {
                 let mode = folder.mode.unwrap_or(SyncMode::Push);
 
                 let (source, target) = match mode {
-                    SyncMode::Push => {
+                    SyncMode::Push | SyncMode::Playlists => {
                         // source = host, target = device
                         let src = PathBuf::from(&folder.source);
                         let dst = PathBuf::from(&device.mount).join(&folder.target);
                         (src, dst)
                     }
-                    SyncMode::Pull => {
+                    SyncMode::Pull | SyncMode::Import | SyncMode::Move => {
                         // source = device, target = host
                         let src = PathBuf::from(&device.mount).join(&folder.source);
                         let dst = PathBuf::from(&folder.target);
@@ -125,13 +441,38 @@ impl AppConfig {
                     device: device.clone(),
                     folder: folder.clone(),
                     index_path,
+                    mount: PathBuf::from(&device.mount),
                     source,
                     target,
-                    mode
+                    mode,
+                    max_bytes_per_sec: device.max_bytes_per_sec.or(self.io.max_bytes_per_sec),
+                    transcode_cache: transcode_cache.clone(),
+                    library: None,
                 });
             }
         }
 
+        // Playlist folders need the task of the folder holding their tracks
+        let resolved = out.clone();
+        for task in out.iter_mut().filter(|t| matches!(t.mode, SyncMode::Playlists)) {
+            let library = task.folder.library.as_deref().and_then(|name| {
+                resolved.iter().find(|l| {
+                    l.device.name == task.device.name
+                        && l.folder.target == name
+                        && matches!(l.mode, SyncMode::Push)
+                })
+            });
+            match library {
+                Some(library) => task.library = Some(Box::new(library.clone())),
+                None => tracing::warn!(
+                    "Playlist folder {} on {}: library {:?} is not a push folder of this device",
+                    task.folder.target,
+                    task.device.name,
+                    task.folder.library
+                ),
+            }
+        }
+
         Ok(out)
     }
 }
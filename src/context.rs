@@ -7,6 +7,7 @@
 use users::{get_current_uid, get_user_by_uid};
 use std::env;
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 
 use crate::config::{AppConfig, DeviceConfig};
 use crate::utils::paths;
@@ -37,6 +38,11 @@ pub struct ExecutionContext {
     pub paths: Paths,
 }
 
+/// Context shared by the daemon threads, replaced as a whole when the
+/// configuration is reloaded. Readers keep the snapshot they got.
+#[derive(Debug, Clone)]
+pub struct SharedContext(Arc<RwLock<Arc<ExecutionContext>>>);
+
 impl EnvContext {
     pub fn gather() -> Self {
         let home = env::var("HOME").unwrap_or_else(|_| "/".into());
@@ -84,3 +90,18 @@ impl ExecutionContext {
      
 }
 
+impl SharedContext {
+    pub fn new(ctx: ExecutionContext) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(ctx))))
+    }
+
+    /// Current context
+    pub fn get(&self) -> Arc<ExecutionContext> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Swap in a new context
+    pub fn replace(&self, ctx: ExecutionContext) {
+        *self.0.write().unwrap() = Arc::new(ctx);
+    }
+}
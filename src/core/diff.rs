@@ -9,6 +9,10 @@ use std::collections::{HashMap, HashSet};
 pub struct Diff {
     pub to_upload: Vec<IndexedFile>,
     pub to_delete: Vec<String>,
+    /// Source files left out because the device cannot read them
+    pub unsupported: Vec<IndexedFile>,
+    /// Synced files outside a profile allowlist, kept on the device
+    pub kept: Vec<String>,
 }
 
 pub fn compute_diff(local: &[IndexedFile], previous: &[IndexedFile]) -> Diff {
@@ -45,5 +49,5 @@ pub fn compute_diff(local: &[IndexedFile], previous: &[IndexedFile]) -> Diff {
         }
     }
 
-    Diff { to_upload, to_delete }
+    Diff { to_upload, to_delete, unsupported: vec![], kept: vec![] }
 }
@@ -10,11 +10,13 @@ use std::fmt;
 use std::path::{Path, PathBuf};
 use anyhow::Result;
 
+use crate::core::tags::Tags;
 use crate::utils::human;
 
 /// Current version of the index file format.
-/// Increment when the struct evolves.
-const INDEX_VERSION: u32 = 1;
+/// Increment when the struct evolves, and keep a frozen copy of the
+/// previous layout in `legacy` so existing indexes can still be read.
+pub const INDEX_VERSION: u32 = 8;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexedFile {
@@ -23,6 +25,38 @@ pub struct IndexedFile {
     pub mtime: i64,
 }
 
+/// Output of a transcoded source file and the settings that produced it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TranscodedFile {
+    pub source: String,
+    pub output: String,
+    pub settings: String,
+}
+
+/// Device file imported on the host (Import and Move modes)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImportedFile {
+    pub hash: String,
+    /// Host copy, relative to the target
+    pub path: String,
+}
+
+/// Tags read from a source file, valid while its size and mtime are unchanged
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedTags {
+    pub path: String,
+    pub size: u64,
+    pub mtime: i64,
+    pub tags: Tags,
+}
+
+/// Target path of a source file laid out from its tags or rewritten
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlacedFile {
+    pub source: String,
+    pub target: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Index {
     pub version: u32,
@@ -37,28 +71,75 @@ pub struct Index {
     pub biggest: Option<IndexedFile>,
     pub newest: Option<IndexedFile>,
     pub oldest: Option<IndexedFile>,
+
+    /// Files already imported, sorted by content hash
+    pub imported: Vec<ImportedFile>,
+
+    /// Target directories holding files put there by plainsync, relative to the target
+    pub dirs: Vec<String>,
+
+    /// Source files stored on the target under another name (transcoding)
+    pub transcoded: Vec<TranscodedFile>,
+
+    /// Playlists generated on the target, relative to the target
+    pub playlists: Vec<String>,
+
+    /// Tags of source files, sorted by path
+    pub tag_cache: Vec<CachedTags>,
+
+    /// Source files placed on the target by a layout template or rewrite rules, sorted by source path
+    pub placed: Vec<PlacedFile>,
+}
+
+/// Parent directories of a relative file path, outermost first:
+/// "a/b/c.flac" gives ["a", "a/b"].
+pub fn parent_dirs(path: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = Path::new(path).parent();
+
+    while let Some(dir) = current {
+        if dir.as_os_str().is_empty() {
+            break;
+        }
+        out.push(dir.to_string_lossy().into_owned());
+        current = dir.parent();
+    }
+
+    out.reverse();
+    out
 }
 
 impl Index {
+    /// Empty index of the current version.
+    fn empty(path: &Path) -> Self {
+        Self {
+            version: INDEX_VERSION,
+            generated_at: Utc::now().timestamp(),
+            files: vec![],
+            path: path.to_path_buf(),
+            total_files: 0,
+            total_size: 0,
+            avg_size: 0,
+            biggest: None,
+            newest: None,
+            oldest: None,
+            imported: vec![],
+            dirs: vec![],
+            transcoded: vec![],
+            playlists: vec![],
+            tag_cache: vec![],
+            placed: vec![],
+        }
+    }
+
     /// Load an index and give it awareness of its own location and existence.
     pub fn load(path: &Path) -> Result<Self> {
         if !path.exists() {
-            return Ok(Self {
-                version: INDEX_VERSION,
-                generated_at: Utc::now().timestamp(),
-                files: vec![],
-                path: path.to_path_buf(),
-                total_files: 0,
-                total_size: 0,
-                avg_size: 0,
-                biggest: None,
-                newest: None,
-                oldest: None,                
-            });
+            return Ok(Self::empty(path));
         }
 
         let raw = fs::read(path)?;
-        let mut idx: Self = bincode::deserialize(&raw)?;
+        let mut idx = Self::decode(&raw)?;
 
         idx.path = path.to_path_buf();
         idx.total_files = idx.files.len();
@@ -75,6 +156,18 @@ impl Index {
         Ok(idx)
     }
 
+    /// Decode any supported on-disk version into the current layout.
+    fn decode(raw: &[u8]) -> Result<Self> {
+        // The version is always the first serialized field
+        let version: u32 = bincode::deserialize(raw)?;
+
+        match version {
+            INDEX_VERSION => Ok(bincode::deserialize(raw)?),
+            1 => Ok(bincode::deserialize::<legacy::IndexV1>(raw)?.into()),
+            v => anyhow::bail!("Unsupported index version {} (expected <= {})", v, INDEX_VERSION),
+        }
+    }
+
     /// Save atomically, using the internally stored path.
     pub fn save(&self) -> Result<()> {
         let tmp = self.path.with_extension("tmp");
@@ -97,6 +190,106 @@ impl Index {
         self.save()
     }    
 
+    /// Record directories plainsync created below `target` (absolute paths).
+    pub fn track_dirs(&mut self, target: &Path, created: &[PathBuf]) {
+        let rel: Vec<String> = created
+            .iter()
+            .filter_map(|d| d.strip_prefix(target).ok())
+            .map(|d| d.to_string_lossy().into_owned())
+            .collect();
+        self.manage_dirs(&rel);
+    }
+
+    /// Record directories plainsync created (relative to the target).
+    pub fn manage_dirs(&mut self, dirs: &[String]) {
+        for dir in dirs {
+            if let Err(pos) = self.dirs.binary_search(dir) {
+                self.dirs.insert(pos, dir.clone());
+            }
+        }
+    }
+
+    /// Transcoding record of a source file, if it was transcoded.
+    pub fn transcoded_as(&self, source: &str) -> Option<&TranscodedFile> {
+        self.transcoded
+            .binary_search_by(|t| t.source.as_str().cmp(source))
+            .ok()
+            .map(|i| &self.transcoded[i])
+    }
+
+    /// Path of a source file on the target.
+    pub fn target_path(&self, source: &str) -> String {
+        if let Some(placed) = self.placed_as(source) {
+            return placed.to_string();
+        }
+        self.transcoded_as(source)
+            .map(|t| t.output.clone())
+            .unwrap_or_else(|| source.to_string())
+    }
+
+    /// Target path given by the layout template or rewrite rules, if any.
+    pub fn placed_as(&self, source: &str) -> Option<&str> {
+        self.placed
+            .binary_search_by(|p| p.source.as_str().cmp(source))
+            .ok()
+            .map(|i| self.placed[i].target.as_str())
+    }
+
+    /// Replace the placement of a source file (None = natural path).
+    /// Placements are kept sorted by source path.
+    pub fn set_placed(&mut self, source: &str, target: Option<String>) {
+        let record = target.map(|target| PlacedFile { source: source.to_string(), target });
+        match (self.placed.binary_search_by(|p| p.source.as_str().cmp(source)), record) {
+            (Ok(i), Some(record)) => self.placed[i] = record,
+            (Ok(i), None) => {
+                self.placed.remove(i);
+            }
+            (Err(i), Some(record)) => self.placed.insert(i, record),
+            (Err(_), None) => {}
+        }
+    }
+
+    /// Replace the transcoding record of a source file (None = stored as is).
+    /// Records are kept sorted by source path.
+    pub fn set_transcoded(&mut self, source: &str, record: Option<TranscodedFile>) {
+        match (self.transcoded.binary_search_by(|t| t.source.as_str().cmp(source)), record) {
+            (Ok(i), Some(record)) => self.transcoded[i] = record,
+            (Ok(i), None) => {
+                self.transcoded.remove(i);
+            }
+            (Err(i), Some(record)) => self.transcoded.insert(i, record),
+            (Err(_), None) => {}
+        }
+    }
+
+    /// Host copy of imported content, if this hash was imported.
+    pub fn imported_as(&self, hash: &str) -> Option<&str> {
+        self.imported
+            .binary_search_by(|i| i.hash.as_str().cmp(hash))
+            .ok()
+            .map(|i| self.imported[i].path.as_str())
+    }
+
+    /// Record where imported content is stored on the host.
+    /// Records are kept sorted by hash.
+    pub fn set_imported(&mut self, hash: &str, path: String) {
+        let record = ImportedFile { hash: hash.to_string(), path };
+        match self.imported.binary_search_by(|i| i.hash.as_str().cmp(hash)) {
+            Ok(i) => self.imported[i] = record,
+            Err(i) => self.imported.insert(i, record),
+        }
+    }
+
+    /// Cached tags of a file, if it did not change since they were read.
+    pub fn cached_tags(&self, file: &IndexedFile) -> Option<&Tags> {
+        self.tag_cache
+            .binary_search_by(|c| c.path.as_str().cmp(&file.path))
+            .ok()
+            .map(|i| &self.tag_cache[i])
+            .filter(|c| c.size == file.size && c.mtime == file.mtime)
+            .map(|c| &c.tags)
+    }
+
     /// Returns whether the index existed before being loaded.
     pub fn exists(&self) -> bool {
         self.path.exists()
@@ -126,3 +319,28 @@ impl fmt::Display for IndexedFile {
         )
     }
 }
+
+/// Frozen layouts of older index versions.
+mod legacy {
+    use super::*;
+
+    /// Version 1: files only. The other data starts empty, in particular no
+    /// directory is managed: plainsync cannot tell which ones it created.
+    #[derive(Deserialize)]
+    pub struct IndexV1 {
+        pub version: u32,
+        pub generated_at: i64,
+        pub files: Vec<IndexedFile>,
+    }
+
+    impl From<IndexV1> for Index {
+        fn from(old: IndexV1) -> Self {
+            tracing::debug!("Upgrading index from version {}", old.version);
+            Index {
+                generated_at: old.generated_at,
+                files: old.files,
+                ..Index::empty(Path::new(""))
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: MIT
+// src/core/journal.rs
+
+//! Write-ahead journal of completed sync operations.
+//! Lets an interrupted sync resume without redoing finished work.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::index::{Index, IndexedFile, TranscodedFile};
+
+/// One completed operation, stored as a JSON line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JournalEntry {
+    Upload { file: IndexedFile },
+    Delete { path: String },
+    /// Device file imported (or skipped as duplicate), with its content hash
+    /// and its host copy (relative to the target)
+    Import { file: IndexedFile, hash: String, stored: String },
+    /// Source file transcoded and stored on the target as `output`
+    Transcode { file: IndexedFile, output: String, settings: String },
+    /// Source file stored (or moved) at a layout path; None = natural path
+    Place { path: String, target: Option<String> },
+    /// Copy to `target` (relative to the target root) started. Only used to
+    /// find the temporary file an interrupted run left behind.
+    Copying { target: String },
+    /// Directories created on the target (relative to its root), managed from now on
+    Dirs { paths: Vec<String> },
+}
+
+impl JournalEntry {
+    /// Whether the entry records a completed operation
+    fn is_operation(&self) -> bool {
+        !matches!(self, JournalEntry::Copying { .. } | JournalEntry::Dirs { .. })
+    }
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Journal {
+    /// Journal stored next to the given index file.
+    pub fn for_index(index_path: &Path) -> Self {
+        Self {
+            path: index_path.with_extension("journal"),
+            file: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Read all complete entries. A truncated trailing line (crash mid-write) is ignored.
+    pub fn read(&self) -> Result<Vec<JournalEntry>> {
+        if !self.exists() {
+            return Ok(vec![]);
+        }
+
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open journal: {}", self.path.display()))?;
+
+        let mut out = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str(&line) {
+                Ok(entry) => out.push(entry),
+                Err(e) => {
+                    tracing::warn!("Ignoring corrupted journal entry in {}: {}", self.path.display(), e);
+                    break;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Append an entry and flush it to disk before returning.
+    pub fn record(&mut self, entry: &JournalEntry) -> Result<()> {
+        if self.file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open journal: {}", self.path.display()))?;
+            self.file = Some(file);
+        }
+
+        let file = self.file.as_mut().unwrap();
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Record the directories created below `target` (absolute paths), if any.
+    pub fn record_dirs(&mut self, target: &Path, created: &[PathBuf]) -> Result<()> {
+        let paths: Vec<String> = created
+            .iter()
+            .filter_map(|d| d.strip_prefix(target).ok())
+            .map(|d| d.to_string_lossy().into_owned())
+            .collect();
+        if paths.is_empty() {
+            return Ok(());
+        }
+        self.record(&JournalEntry::Dirs { paths })
+    }
+
+    /// Fold pending entries into a file list, in memory only.
+    /// Returns the number of operations applied.
+    pub fn fold(&self, files: &mut Vec<IndexedFile>) -> Result<usize> {
+        let entries = self.read()?;
+        apply(&entries, files);
+        Ok(entries.iter().filter(|e| e.is_operation()).count())
+    }
+
+    /// Number of completed operations not yet folded into the index
+    pub fn operations(&self) -> Result<usize> {
+        Ok(self.read()?.iter().filter(|e| e.is_operation()).count())
+    }
+
+    /// Target paths of the copies started by an interrupted run
+    pub fn in_progress(&self) -> Result<Vec<String>> {
+        Ok(self
+            .read()?
+            .into_iter()
+            .filter_map(|e| match e {
+                JournalEntry::Copying { target } => Some(target),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Fold pending entries into the index, save it and clear the journal.
+    /// Returns the number of operations recovered.
+    pub fn replay(&mut self, idx: &mut Index) -> Result<usize> {
+        let entries = self.read()?;
+
+        if !entries.is_empty() {
+            let mut files = std::mem::take(&mut idx.files);
+            apply(&entries, &mut files);
+
+            for entry in &entries {
+                match entry {
+                    JournalEntry::Upload { file } => {
+                        idx.set_transcoded(&file.path, None);
+                        idx.set_placed(&file.path, None);
+                    }
+                    JournalEntry::Transcode { file, output, settings } => {
+                        idx.set_placed(&file.path, None);
+                        idx.set_transcoded(&file.path, Some(TranscodedFile {
+                            source: file.path.clone(),
+                            output: output.clone(),
+                            settings: settings.clone(),
+                        }));
+                    }
+                    JournalEntry::Place { path, target } => {
+                        idx.set_placed(path, target.clone());
+                    }
+                    JournalEntry::Delete { path } => {
+                        idx.set_transcoded(path, None);
+                        idx.set_placed(path, None);
+                    }
+                    JournalEntry::Import { hash, stored, .. } => {
+                        idx.set_imported(hash, stored.clone());
+                    }
+                    JournalEntry::Dirs { paths } => idx.manage_dirs(paths),
+                    _ => {}
+                }
+            }
+            idx.update(files)?;
+        }
+        self.clear()?;
+
+        Ok(entries.iter().filter(|e| e.is_operation()).count())
+    }
+
+    /// Remove the journal once its content is part of the index.
+    pub fn clear(&mut self) -> Result<()> {
+        self.file = None;
+        if self.exists() {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("Failed to remove journal: {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply entries, in order, to a file list.
+fn apply(entries: &[JournalEntry], files: &mut Vec<IndexedFile>) {
+    for entry in entries {
+        match entry {
+            JournalEntry::Upload { file }
+            | JournalEntry::Import { file, .. }
+            | JournalEntry::Transcode { file, .. } => {
+                match files.iter_mut().find(|f| f.path == file.path) {
+                    Some(existing) => *existing = file.clone(),
+                    None => files.push(file.clone()),
+                }
+            }
+            JournalEntry::Delete { path } => files.retain(|f| f.path != *path),
+            JournalEntry::Place { .. } | JournalEntry::Copying { .. } | JournalEntry::Dirs { .. } => {}
+        }
+    }
+}
@@ -5,5 +5,7 @@
 
 pub mod diff;
 pub mod index;
+pub mod journal;
 pub mod scanner;
-pub mod task;
\ No newline at end of file
+pub mod tags;
+pub mod task;
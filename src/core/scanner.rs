@@ -3,25 +3,84 @@
 
 //! Sanning local filesystem and producing file indexes.
 
+use crate::config::SymlinkPolicy;
 use crate::core::index::IndexedFile;
-use walkdir::WalkDir;
+use crate::utils::heartbeat::pulse;
+use walkdir::{DirEntry, WalkDir};
 use anyhow::Result;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::FileTypeExt;
 use std::path::Path;
 
+/// Scan `root` with the default symlink policy (skip).
 pub fn scan_local(root: &Path) -> Result<Vec<IndexedFile>> {
+    scan_local_with(root, SymlinkPolicy::default())
+}
+
+/// Scan `root`, handling symlinks according to `symlinks`.
+/// Loops, broken links and special files are reported as warnings and skipped.
+pub fn scan_local_with(root: &Path, symlinks: SymlinkPolicy) -> Result<Vec<IndexedFile>> {
     let mut out = Vec::new();
 
-    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let meta = entry.metadata()?;
-            let size = meta.len();
-            let mtime = meta.modified()?.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+    let walker = WalkDir::new(root).follow_links(symlinks == SymlinkPolicy::Follow);
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                match (e.loop_ancestor(), e.path()) {
+                    (Some(ancestor), Some(path)) => tracing::warn!(
+                        "Skipping symlink loop: {} points back to {}",
+                        path.display(),
+                        ancestor.display()
+                    ),
+                    (_, Some(path)) => tracing::warn!("Skipping {}: {}", path.display(), e),
+                    _ => tracing::warn!("Skipping unreadable entry: {}", e),
+                }
+                continue;
+            }
+        };
+
+        let Some(meta) = file_metadata(&entry, symlinks)? else {
+            continue;
+        };
 
-            let rel = entry.path().strip_prefix(root)?.to_string_lossy().to_string();
+        let size = meta.len();
+        let mtime = meta.modified()?.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
 
-            out.push(IndexedFile { path: rel, size, mtime });
-        }
+        let rel = entry.path().strip_prefix(root)?.to_string_lossy().to_string();
+
+        out.push(IndexedFile { path: rel, size, mtime });
+        pulse();
     }
 
     Ok(out)
 }
+
+/// Metadata of the regular file behind an entry, None when it must not be synced.
+fn file_metadata(entry: &DirEntry, symlinks: SymlinkPolicy) -> Result<Option<Metadata>> {
+    let ft = entry.file_type();
+
+    if ft.is_file() {
+        return Ok(Some(entry.metadata()?));
+    }
+
+    if ft.is_fifo() || ft.is_socket() || ft.is_block_device() || ft.is_char_device() {
+        tracing::warn!("Skipping special file: {}", entry.path().display());
+        return Ok(None);
+    }
+
+    // Only reached for links that were not followed by the walker
+    if ft.is_symlink() && symlinks == SymlinkPolicy::CopyTarget {
+        return match fs::metadata(entry.path()) {
+            Ok(meta) if meta.is_file() => Ok(Some(meta)),
+            Ok(_) => Ok(None),
+            Err(e) => {
+                tracing::warn!("Skipping broken symlink {}: {}", entry.path().display(), e);
+                Ok(None)
+            }
+        };
+    }
+
+    Ok(None)
+}
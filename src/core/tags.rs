@@ -0,0 +1,600 @@
+// SPDX-License-Identifier: MIT
+// src/core/tags.rs
+
+//! Minimal reader for embedded audio tags: ID3v1/ID3v2 (MP3), Vorbis
+//! comments (FLAC, Ogg Vorbis, Opus) and MP4 item atoms (M4A).
+//! Only text fields are read; pictures and other binary data are skipped.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Largest metadata block read into memory (cover art excluded where possible)
+const MAX_BLOCK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// File extensions the reader understands
+pub const TAGGED_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "oga", "opus", "m4a", "m4b", "mp4"];
+
+/// Standard ID3v1 genres, referenced by number in old tags
+const ID3_GENRES: &[&str] = &[
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz",
+    "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno",
+    "Industrial", "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno",
+    "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance", "Classical", "Instrumental",
+    "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise", "AlternRock", "Bass", "Soul", "Punk",
+    "Space", "Meditative", "Instrumental Pop", "Instrumental Rock", "Ethnic", "Gothic", "Darkwave",
+    "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream", "Southern Rock", "Comedy",
+    "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk", "Jungle", "Native American",
+    "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer", "Lo-Fi", "Tribal",
+    "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+];
+
+/// Tags of one file. Keys are lowercase and normalised across formats:
+/// title, artist, albumartist, album, genre, year, track, disc, rating (0-5),
+/// plus any custom key (e.g. "device"). A key may hold several values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tags(pub BTreeMap<String, Vec<String>>);
+
+impl Tags {
+    /// First value of a tag
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.first()).map(String::as_str)
+    }
+
+    /// All values of a tag
+    pub fn values(&self, key: &str) -> &[String] {
+        self.0.get(key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add a value under its normalised key, ignoring empty values
+    pub fn insert(&mut self, key: &str, value: &str) {
+        let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        if value.is_empty() {
+            return;
+        }
+
+        let key = normalize_key(key);
+        let value = match key.as_str() {
+            "year" => value.chars().take(4).collect(),
+            "rating" => match normalize_rating(value) {
+                Some(r) => r.to_string(),
+                None => return,
+            },
+            _ => value.to_string(),
+        };
+
+        let values = self.0.entry(key).or_default();
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+}
+
+/// Whether the reader supports a file, by extension
+pub fn is_tagged_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| TAGGED_EXTENSIONS.iter().any(|x| e.to_string_lossy().eq_ignore_ascii_case(x)))
+}
+
+/// Read the tags of a file. Unsupported or untagged files give empty tags.
+pub fn read_tags(path: &Path) -> Result<Tags> {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+
+    let mut file = File::open(path)?;
+    let mut tags = Tags::default();
+
+    match ext.as_str() {
+        "mp3" => {
+            // ID3v1 is only a fallback for files without ID3v2
+            let found = read_id3v2(&mut file, &mut tags)?;
+            if !found {
+                read_id3v1(&mut file, &mut tags)?;
+            }
+        }
+        "flac" => read_flac(&mut file, &mut tags)?,
+        "ogg" | "oga" | "opus" => read_ogg(&mut file, &mut tags)?,
+        "m4a" | "m4b" | "mp4" => read_mp4(&mut file, &mut tags)?,
+        _ => {}
+    }
+
+    Ok(tags)
+}
+
+/// Common key for the same field across formats
+fn normalize_key(key: &str) -> String {
+    let key = key.to_ascii_lowercase();
+    match key.as_str() {
+        "album artist" | "album_artist" => "albumartist".into(),
+        "date" | "originaldate" => "year".into(),
+        "tracknumber" => "track".into(),
+        "discnumber" => "disc".into(),
+        _ => key,
+    }
+}
+
+/// Ratings on a 0-5 scale, from 0-5 or 0-100 values
+fn normalize_rating(value: &str) -> Option<u8> {
+    let n: f64 = value.trim().parse().ok()?;
+    let stars = if n <= 5.0 { n } else { n / 20.0 };
+    Some(stars.round().clamp(0.0, 5.0) as u8)
+}
+
+/// POPM ratings (0-255) on a 0-5 scale, following common player conventions
+fn popm_rating(byte: u8) -> u8 {
+    match byte {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    }
+}
+
+fn read_exact_vec(file: &mut File, len: u64) -> Result<Vec<u8>> {
+    if len > MAX_BLOCK_SIZE {
+        anyhow::bail!("Metadata block too large ({} bytes)", len);
+    }
+    let mut buf = vec![0; len as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn be_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn synchsafe(b: &[u8]) -> u32 {
+    b.iter().take(4).fold(0, |acc, x| (acc << 7) | (*x as u32 & 0x7f))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| char::from(*b)).collect()
+}
+
+fn utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+// ---------------------------------------------------------------- ID3
+
+/// Decode an ID3v2 text payload (leading encoding byte). Values are NUL-separated.
+fn id3_text(data: &[u8]) -> Vec<String> {
+    let Some((&encoding, body)) = data.split_first() else {
+        return vec![];
+    };
+
+    let text = match encoding {
+        0 => latin1(body),
+        1 => match body {
+            [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+            [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+            _ => utf16(body, false),
+        },
+        2 => utf16(body, true),
+        _ => String::from_utf8_lossy(body).into_owned(),
+    };
+
+    text.split('\0').map(|s| s.trim_start_matches('\u{feff}').to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+/// "(17)", "17" or "(17)Rock" genre references
+fn id3_genre(value: &str) -> String {
+    let inner = value.strip_prefix('(').and_then(|v| v.split_once(')'));
+    let (number, rest) = match inner {
+        Some((n, rest)) => (n, rest),
+        None => (value, ""),
+    };
+
+    if !rest.is_empty() {
+        return rest.to_string();
+    }
+    match number.parse::<usize>().ok().and_then(|n| ID3_GENRES.get(n)) {
+        Some(genre) => genre.to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Key of a text frame (ID3v2.3/2.4 and 2.2 ids)
+fn id3_key(id: &str) -> Option<&'static str> {
+    Some(match id {
+        "TIT2" | "TT2" => "title",
+        "TPE1" | "TP1" => "artist",
+        "TPE2" | "TP2" => "albumartist",
+        "TALB" | "TAL" => "album",
+        "TCON" | "TCO" => "genre",
+        "TYER" | "TDRC" | "TYE" | "TDOR" => "year",
+        "TRCK" | "TRK" => "track",
+        "TPOS" | "TPA" => "disc",
+        _ => return None,
+    })
+}
+
+/// Read an ID3v2 tag at the start of the file. Returns whether one was found.
+fn read_id3v2(file: &mut File, tags: &mut Tags) -> Result<bool> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(false);
+    }
+
+    let major = header[3];
+    let flags = header[5];
+    let mut data = read_exact_vec(file, synchsafe(&header[6..10]) as u64)?;
+
+    // Unsynchronisation: every 0xFF 0x00 pair stands for 0xFF
+    if flags & 0x80 != 0 {
+        let mut out = Vec::with_capacity(data.len());
+        let mut prev = 0u8;
+        for b in data {
+            if !(prev == 0xff && b == 0) {
+                out.push(b);
+            }
+            prev = b;
+        }
+        data = out;
+    }
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 && major >= 3 && data.len() >= 4 {
+        pos = match major {
+            3 => 4 + be_u32(&data[..4]) as usize,
+            _ => synchsafe(&data[..4]) as usize,
+        };
+    }
+
+    let (id_len, header_len) = if major == 2 { (3, 6) } else { (4, 10) };
+
+    while pos + header_len <= data.len() {
+        let id = String::from_utf8_lossy(&data[pos..pos + id_len]).into_owned();
+        if !id.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            break; // padding
+        }
+
+        let size = match major {
+            2 => u32::from_be_bytes([0, data[pos + 3], data[pos + 4], data[pos + 5]]),
+            3 => be_u32(&data[pos + 4..pos + 8]),
+            _ => synchsafe(&data[pos + 4..pos + 8]),
+        } as usize;
+
+        let start = pos + header_len;
+        let end = (start + size).min(data.len());
+        let body = &data[start..end];
+        pos = start + size;
+
+        match id.as_str() {
+            "TXXX" | "TXX" => {
+                let values = id3_text(body);
+                if let Some((desc, rest)) = values.split_first() {
+                    for v in rest {
+                        tags.insert(desc, v);
+                    }
+                }
+            }
+            "POPM" | "POP" => {
+                // email, NUL, rating byte, play counter
+                if let Some(nul) = body.iter().position(|b| *b == 0) {
+                    if let Some(rating) = body.get(nul + 1) {
+                        tags.insert("rating", &popm_rating(*rating).to_string());
+                    }
+                }
+            }
+            _ => {
+                if let Some(key) = id3_key(&id) {
+                    for v in id3_text(body) {
+                        let v = if key == "genre" { id3_genre(&v) } else { v };
+                        tags.insert(key, &v);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+/// Read the 128-byte ID3v1 tag at the end of the file
+fn read_id3v1(file: &mut File, tags: &mut Tags) -> Result<()> {
+    if file.metadata()?.len() < 128 {
+        return Ok(());
+    }
+    file.seek(SeekFrom::End(-128))?;
+    let mut buf = [0u8; 128];
+    file.read_exact(&mut buf)?;
+
+    if &buf[..3] != b"TAG" {
+        return Ok(());
+    }
+
+    tags.insert("title", &latin1(&buf[3..33]));
+    tags.insert("artist", &latin1(&buf[33..63]));
+    tags.insert("album", &latin1(&buf[63..93]));
+    tags.insert("year", &latin1(&buf[93..97]));
+    // ID3v1.1: a zero byte before the last comment byte marks a track number
+    if buf[125] == 0 && buf[126] != 0 {
+        tags.insert("track", &buf[126].to_string());
+    }
+    if let Some(genre) = ID3_GENRES.get(buf[127] as usize) {
+        tags.insert("genre", genre);
+    }
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------- Vorbis comments
+
+/// Parse a Vorbis comment block (vendor string, then KEY=value entries)
+fn vorbis_comments(data: &[u8], tags: &mut Tags) {
+    let mut pos = 0;
+    let mut next = |len: usize| -> Option<&[u8]> {
+        let out = data.get(pos..pos + len)?;
+        pos += len;
+        Some(out)
+    };
+
+    let Some(vendor_len) = next(4).map(le_u32) else {
+        return;
+    };
+    if next(vendor_len as usize).is_none() {
+        return;
+    }
+    let Some(count) = next(4).map(le_u32) else {
+        return;
+    };
+
+    for _ in 0..count {
+        let Some(len) = next(4).map(le_u32) else {
+            return;
+        };
+        let Some(entry) = next(len as usize) else {
+            return;
+        };
+        let entry = String::from_utf8_lossy(entry);
+        if let Some((key, value)) = entry.split_once('=') {
+            if !key.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE") {
+                tags.insert(key, value);
+            }
+        }
+    }
+}
+
+/// FLAC: metadata blocks after the "fLaC" marker (possibly behind an ID3v2 tag)
+fn read_flac(file: &mut File, tags: &mut Tags) -> Result<()> {
+    let mut start = 0;
+    file.seek(SeekFrom::Start(0))?;
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_ok() && &header[..3] == b"ID3" {
+        start = 10 + synchsafe(&header[6..10]) as u64;
+    }
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut marker = [0u8; 4];
+    if file.read_exact(&mut marker).is_err() || &marker != b"fLaC" {
+        return Ok(());
+    }
+
+    loop {
+        let mut block = [0u8; 4];
+        if file.read_exact(&mut block).is_err() {
+            break;
+        }
+        let last = block[0] & 0x80 != 0;
+        let kind = block[0] & 0x7f;
+        let len = u32::from_be_bytes([0, block[1], block[2], block[3]]) as u64;
+
+        if kind == 4 {
+            vorbis_comments(&read_exact_vec(file, len)?, tags);
+            break;
+        }
+        file.seek(SeekFrom::Current(len as i64))?;
+
+        if last {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Ogg: the second packet of the first stream holds the comments
+/// ("\x03vorbis" for Vorbis, "OpusTags" for Opus).
+fn read_ogg(file: &mut File, tags: &mut Tags) -> Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut packets: Vec<Vec<u8>> = vec![];
+    let mut current = vec![];
+    let mut serial = None;
+
+    while packets.len() < 2 {
+        let mut header = [0u8; 27];
+        if file.read_exact(&mut header).is_err() || &header[..4] != b"OggS" {
+            break;
+        }
+        let page_serial = le_u32(&header[14..18]);
+        let mut segments = vec![0u8; header[26] as usize];
+        file.read_exact(&mut segments)?;
+        let body_len: u64 = segments.iter().map(|s| *s as u64).sum();
+
+        if *serial.get_or_insert(page_serial) != page_serial {
+            file.seek(SeekFrom::Current(body_len as i64))?;
+            continue;
+        }
+
+        let body = read_exact_vec(file, body_len)?;
+        let mut pos = 0;
+        for seg in segments {
+            current.extend_from_slice(&body[pos..pos + seg as usize]);
+            pos += seg as usize;
+            if seg < 255 {
+                packets.push(std::mem::take(&mut current));
+            }
+        }
+
+        if current.len() as u64 > MAX_BLOCK_SIZE {
+            anyhow::bail!("Ogg comment packet too large");
+        }
+    }
+
+    if let Some(packet) = packets.get(1) {
+        if let Some(rest) = packet.strip_prefix(b"\x03vorbis") {
+            vorbis_comments(rest, tags);
+        } else if let Some(rest) = packet.strip_prefix(b"OpusTags") {
+            vorbis_comments(rest, tags);
+        }
+    }
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------- MP4
+
+/// Child atoms of an atom body: (type, body)
+fn mp4_atoms(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut out = vec![];
+    let mut pos = 0;
+
+    while pos + 8 <= data.len() {
+        let mut size = be_u32(&data[pos..pos + 4]) as usize;
+        let kind = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        let mut header = 8;
+
+        if size == 1 && pos + 16 <= data.len() {
+            let mut wide = [0u8; 8];
+            wide.copy_from_slice(&data[pos + 8..pos + 16]);
+            size = u64::from_be_bytes(wide) as usize;
+            header = 16;
+        } else if size == 0 {
+            size = data.len() - pos;
+        }
+
+        // Sizes come from the file: a wide size can point past any buffer
+        let Some(end) = pos.checked_add(size).filter(|end| size >= header && *end <= data.len())
+        else {
+            break;
+        };
+        out.push((kind, &data[pos + header..end]));
+        pos = end;
+    }
+
+    out
+}
+
+fn mp4_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    mp4_atoms(data).into_iter().find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+/// Key of an MP4 item atom
+fn mp4_key(kind: &[u8; 4]) -> Option<&'static str> {
+    Some(match kind {
+        b"\xa9nam" => "title",
+        b"\xa9ART" => "artist",
+        b"aART" => "albumartist",
+        b"\xa9alb" => "album",
+        b"\xa9gen" => "genre",
+        b"\xa9day" => "year",
+        b"trkn" => "track",
+        b"disk" => "disc",
+        b"rate" => "rating",
+        _ => return None,
+    })
+}
+
+/// MP4: moov/udta/meta/ilst items, each with a "data" child
+fn read_mp4(file: &mut File, tags: &mut Tags) -> Result<()> {
+    let len = file.metadata()?.len();
+    let mut pos = 0;
+    file.seek(SeekFrom::Start(0))?;
+
+    // Walk top-level atoms without reading the media data
+    let moov = loop {
+        if pos + 8 > len {
+            return Ok(());
+        }
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let mut size = be_u32(&header[..4]) as u64;
+        let mut header_len = 8;
+
+        if size == 1 {
+            if pos + 16 > len {
+                return Ok(());
+            }
+            let mut wide = [0u8; 8];
+            file.read_exact(&mut wide)?;
+            size = u64::from_be_bytes(wide);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        let Some(end) = pos.checked_add(size).filter(|end| size >= header_len && *end <= len)
+        else {
+            return Ok(());
+        };
+
+        if &header[4..8] == b"moov" {
+            break read_exact_vec(file, size - header_len)?;
+        }
+        pos = end;
+        file.seek(SeekFrom::Start(pos))?;
+    };
+
+    let Some(meta) = mp4_child(&moov, b"udta").and_then(|u| mp4_child(u, b"meta")) else {
+        return Ok(());
+    };
+    // "meta" is a full atom: version and flags come first
+    let Some(ilst) = meta.get(4..).and_then(|m| mp4_child(m, b"ilst")) else {
+        return Ok(());
+    };
+
+    for (kind, item) in mp4_atoms(ilst) {
+        if &kind == b"----" {
+            // Freeform: mean, name, data
+            let name = mp4_child(item, b"name")
+                .and_then(|n| n.get(4..))
+                .map(|n| String::from_utf8_lossy(n).into_owned());
+            if let (Some(name), Some(data)) = (name, mp4_child(item, b"data")) {
+                if let Some(value) = data.get(8..) {
+                    tags.insert(&name, &String::from_utf8_lossy(value));
+                }
+            }
+            continue;
+        }
+
+        let Some(key) = mp4_key(&kind) else { continue };
+        let Some(data) = mp4_child(item, b"data") else {
+            continue;
+        };
+        let Some(value) = data.get(8..) else { continue };
+        let type_code = data.get(3).copied().unwrap_or(0);
+
+        match key {
+            // Binary pairs: padding, number, total
+            "track" | "disc" if value.len() >= 4 => {
+                tags.insert(key, &u16::from_be_bytes([value[2], value[3]]).to_string());
+            }
+            _ if type_code == 21 || (type_code == 0 && key == "rating") => {
+                let n = value.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                tags.insert(key, &n.to_string());
+            }
+            _ => tags.insert(key, &String::from_utf8_lossy(value)),
+        }
+    }
+
+    Ok(())
+}
@@ -19,17 +19,34 @@ pub struct SyncTask {
 
     // runtime-only
     pub index_path: PathBuf,
+    pub mount: PathBuf,
     pub source: PathBuf,
     pub target: PathBuf,
     pub mode: SyncMode,
+    pub max_bytes_per_sec: Option<u64>,
+    pub transcode_cache: PathBuf,
+    pub library: Option<Box<SyncTask>>, // Playlists: folder holding the tracks
 }
 
 impl SyncTask {
+    /// Whether `other` syncs the same folder the same way, so a configuration
+    /// reload can leave it alone
+    pub fn same_sync(&self, other: &SyncTask) -> bool {
+        self.folder == other.folder
+            && self.source == other.source
+            && self.target == other.target
+            && self.device.filesystem == other.device.filesystem
+            && self.device.formats == other.device.formats
+    }
+
     // Return a new SyncTask with expanded source and target paths.
     pub fn expanded(&self, ctx: &ExecutionContext) -> Self {
         SyncTask {
+            mount: ctx.expand_mount(&self.device),
             source: ctx.expander.expand(self.source.to_str().unwrap(), &self.device.name),
             target: ctx.expander.expand(self.target.to_str().unwrap(), &self.device.name),
+            transcode_cache: ctx.expander.expand(self.transcode_cache.to_str().unwrap(), &self.device.name),
+            library: self.library.as_ref().map(|l| Box::new(l.expanded(ctx))),
             ..self.clone()
         }
     }
@@ -40,10 +57,51 @@ impl SyncTask {
     }
 }
 
+/// Include/exclude rules used to run only part of the plan.
+/// Folders are selected by their configured `target`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskSelector {
+    pub devices: Vec<String>,
+    pub folders: Vec<String>,
+    pub tags: Vec<String>,
+    pub exclude_devices: Vec<String>,
+    pub exclude_folders: Vec<String>,
+    pub exclude_tags: Vec<String>,
+}
+
+impl TaskSelector {
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+            && self.folders.is_empty()
+            && self.tags.is_empty()
+            && self.exclude_devices.is_empty()
+            && self.exclude_folders.is_empty()
+            && self.exclude_tags.is_empty()
+    }
+
+    // A task must match every non-empty include list and no exclude list.
+    pub fn matches(&self, task: &SyncTask) -> bool {
+        let device = &task.device.name;
+        let folder = &task.folder.target;
+        let tags = &task.folder.tags;
+
+        let included = (self.devices.is_empty() || self.devices.contains(device))
+            && (self.folders.is_empty() || self.folders.contains(folder))
+            && (self.tags.is_empty() || tags.iter().any(|t| self.tags.contains(t)));
+
+        let excluded = self.exclude_devices.contains(device)
+            || self.exclude_folders.contains(folder)
+            || tags.iter().any(|t| self.exclude_tags.contains(t));
+
+        included && !excluded
+    }
+}
+
 pub trait SyncTaskFilter {
     fn filter_by_device(self, name: &str) -> Vec<SyncTask>;
     fn filter_by_devices(self, names: &std::collections::HashSet<&str>) -> Vec<SyncTask>;
     fn filter_enabled(self) -> Vec<SyncTask>;
+    fn filter_selected(self, selector: &TaskSelector) -> Vec<SyncTask>;
 }
 
 impl SyncTaskFilter for Vec<SyncTask> {
@@ -67,6 +125,13 @@ impl SyncTaskFilter for Vec<SyncTask> {
             .filter(|item| item.folder.enabled)
             .collect()
     }
+
+    // Filter SyncTasks to only those matching the selector.
+    fn filter_selected(self, selector: &TaskSelector) -> Vec<SyncTask> {
+        self.into_iter()
+            .filter(|item| selector.matches(item))
+            .collect()
+    }
 }
 
 pub trait SyncTaskExpand {
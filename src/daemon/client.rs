@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/client.rs
+
+//! Client of the daemon control socket, for the CLI and other tools.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::BufReader;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::daemon::protocol::{receive, send, DaemonStatus, Event, Request, Response, SyncResult};
+use crate::utils::paths::control_socket;
+
+pub struct DaemonClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon of the current user
+    pub fn connect() -> Result<Self> {
+        Self::connect_to(&control_socket())
+    }
+
+    /// Connection to a daemon that answers within `timeout`, if one is running
+    pub fn probe(timeout: Duration) -> Option<Self> {
+        let mut client = Self::connect().ok()?;
+        client.writer.set_read_timeout(Some(timeout)).ok()?;
+        client.status().ok()?;
+        client.writer.set_read_timeout(None).ok()?;
+        Some(client)
+    }
+
+    /// Connect to the socket at `path`, refusing one another user created
+    pub fn connect_to(path: &Path) -> Result<Self> {
+        if let Ok(meta) = fs::metadata(path) {
+            if meta.uid() != users::get_current_uid() {
+                anyhow::bail!("{} belongs to another user, not connecting", path.display());
+            }
+        }
+
+        let writer = UnixStream::connect(path)
+            .with_context(|| format!("No daemon listening on {}", path.display()))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self { reader, writer })
+    }
+
+    /// Send a request and read its response. Error responses become errors.
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        send(&mut self.writer, request)?;
+        match receive(&mut self.reader)? {
+            Some(Response::Error { message }) => anyhow::bail!("Daemon error: {}", message),
+            Some(response) => Ok(response),
+            None => anyhow::bail!("Daemon closed the connection"),
+        }
+    }
+
+    pub fn status(&mut self) -> Result<DaemonStatus> {
+        match self.request(&Request::Status)? {
+            Response::Status(status) => Ok(status),
+            other => anyhow::bail!("Unexpected daemon response: {:?}", other),
+        }
+    }
+
+    /// Start syncs in the background, returns how many were started
+    pub fn sync(&mut self, device: Option<&str>, folder: Option<&str>, max_bytes_per_sec: Option<u64>) -> Result<usize> {
+        match self.request(&sync_request(device, folder, false, max_bytes_per_sec))? {
+            Response::Queued { tasks } => Ok(tasks),
+            other => anyhow::bail!("Unexpected daemon response: {:?}", other),
+        }
+    }
+
+    /// Run syncs and wait for their results
+    pub fn sync_wait(&mut self, device: Option<&str>, folder: Option<&str>, max_bytes_per_sec: Option<u64>) -> Result<Vec<SyncResult>> {
+        match self.request(&sync_request(device, folder, true, max_bytes_per_sec))? {
+            Response::Synced { results } => Ok(results),
+            other => anyhow::bail!("Unexpected daemon response: {:?}", other),
+        }
+    }
+
+    pub fn pause(&mut self) -> Result<()> {
+        self.request(&Request::Pause).map(|_| ())
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.request(&Request::Resume).map(|_| ())
+    }
+
+    /// Turn the connection into an event stream
+    pub fn subscribe(mut self) -> Result<EventStream> {
+        match self.request(&Request::Subscribe)? {
+            Response::Subscribed => Ok(EventStream { reader: self.reader }),
+            other => anyhow::bail!("Unexpected daemon response: {:?}", other),
+        }
+    }
+}
+
+fn sync_request(device: Option<&str>, folder: Option<&str>, wait: bool, max_bytes_per_sec: Option<u64>) -> Request {
+    Request::Sync {
+        device: device.map(str::to_string),
+        folder: folder.map(str::to_string),
+        wait,
+        max_bytes_per_sec,
+    }
+}
+
+/// Events of a subscribed connection, until the daemon closes it
+pub struct EventStream {
+    reader: BufReader<UnixStream>,
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        receive(&mut self.reader).transpose()
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/control.rs
+
+//! Control socket server: answers client requests with the DaemonState.
+//! Each connection is served by its own thread.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::BufReader;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use crate::daemon::protocol::{receive, send, Request, Response};
+use crate::daemon::state::DaemonState;
+use crate::utils::paths::private_dir;
+
+/// Listen on `path` and serve requests in the background.
+/// Fails when another daemon already answers on it.
+pub fn start_control_socket(path: &Path, state: DaemonState) -> Result<()> {
+    // Bound inside a private directory: the socket is never reachable by others,
+    // even before its own permissions are set
+    if let Some(parent) = path.parent() {
+        private_dir(parent)?;
+    }
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("Another daemon is already listening on {}", path.display());
+        }
+        // Left over by a daemon that did not exit cleanly
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    tracing::info!("Control socket listening on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let state = state.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve(&state, stream) {
+                            tracing::debug!("Control connection closed: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => tracing::warn!("Control socket error: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Answer the requests of one connection until it is closed
+fn serve(state: &DaemonState, stream: UnixStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let request = match receive::<Request>(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                send(&mut writer, &Response::Error { message: format!("{:#}", e) })?;
+                continue;
+            }
+        };
+
+        if request == Request::Subscribe {
+            let events = state.subscribe();
+            send(&mut writer, &Response::Subscribed)?;
+            for event in events {
+                send(&mut writer, &event)?;
+            }
+            return Ok(());
+        }
+
+        send(&mut writer, &respond(state, request))?;
+    }
+}
+
+fn respond(state: &DaemonState, request: Request) -> Response {
+    match request {
+        Request::Status => Response::Status(state.status()),
+
+        Request::Sync { device, folder, wait, max_bytes_per_sec } => {
+            if state.is_stopping() {
+                return Response::Error { message: "Daemon is shutting down".into() };
+            }
+            if state.is_paused() {
+                return Response::Error { message: "Daemon is paused, resume it first".into() };
+            }
+            let selected = state.select(device.as_deref(), folder.as_deref()).map(|mut tasks| {
+                if let Some(limit) = max_bytes_per_sec {
+                    tasks.iter_mut().for_each(|t| t.max_bytes_per_sec = Some(limit));
+                }
+                tasks
+            });
+            match selected {
+                Ok(tasks) if wait => Response::Synced { results: state.run_syncs(tasks) },
+                Ok(tasks) => {
+                    let count = tasks.len();
+                    let state = state.clone();
+                    thread::spawn(move || state.run_syncs(tasks));
+                    Response::Queued { tasks: count }
+                }
+                Err(e) => Response::Error { message: format!("{:#}", e) },
+            }
+        }
+
+        Request::Pause => {
+            state.pause();
+            Response::Paused
+        }
+
+        Request::Resume => {
+            state.resume();
+            Response::Resumed
+        }
+
+        Request::Subscribe => Response::Subscribed,
+    }
+}
@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/events.rs
+
+//! Broadcast of daemon events to control socket subscribers.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::daemon::protocol::Event;
+
+#[derive(Clone, Debug, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<Event>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Send an event to all subscribers, forgetting those that are gone
+    pub fn publish(&self, event: Event) {
+        self.subscribers.lock().unwrap().retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
@@ -4,3 +4,10 @@
 //! Daemon module for Plainsync application.
 
 pub mod state;
+pub mod protocol;
+pub mod events;
+pub mod control;
+pub mod client;
+pub mod reload;
+pub mod signals;
+pub mod watchdog;
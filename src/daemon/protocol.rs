@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/protocol.rs
+
+//! Control socket protocol: one JSON object per line. Clients send a
+//! request and read one response; after `subscribe`, the daemon keeps
+//! writing events on the connection.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// Request sent by a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Active syncs, progress and last results
+    Status,
+    /// Sync the active folders of a device, or one folder (by target).
+    /// With `wait`, the response is sent once the syncs are done.
+    Sync {
+        #[serde(default)]
+        device: Option<String>,
+        #[serde(default)]
+        folder: Option<String>,
+        #[serde(default)]
+        wait: bool,
+        /// Overrides the configured bandwidth limit (0 = unlimited)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_bytes_per_sec: Option<u64>,
+    },
+    /// Stop syncing on changes; they are kept for `resume`
+    Pause,
+    /// Sync the changes kept while paused and follow new ones again
+    Resume,
+    /// Stream events on this connection
+    Subscribe,
+}
+
+/// Response to a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Status(DaemonStatus),
+    /// Syncs started in the background
+    Queued { tasks: usize },
+    /// Syncs done, for a request with `wait`
+    Synced { results: Vec<SyncResult> },
+    Paused,
+    Resumed,
+    /// Events follow on the connection
+    Subscribed,
+    Error { message: String },
+}
+
+/// Event streamed to subscribers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    DeviceMounted { device: String },
+    DeviceUnmounted { device: String },
+    SyncStarted { device: String, folder: String },
+    SyncFinished(SyncResult),
+    Paused,
+    Resumed,
+    ConfigReloaded,
+    /// Invalid configuration edit, the previous one is kept
+    ConfigError { message: String },
+}
+
+/// State of the daemon
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub paused: bool,
+    /// Folders of the mounted devices
+    pub active: Vec<ActiveSync>,
+    /// Sync in progress
+    pub running: Option<SyncProgress>,
+    /// Folders waiting for `resume`
+    pub deferred: usize,
+    /// Last result of each folder
+    pub results: Vec<SyncResult>,
+}
+
+/// Folder synced by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveSync {
+    pub device: String,
+    pub folder: String,
+    pub source: String,
+    pub target: String,
+}
+
+/// Progress of the sync in progress
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    pub device: String,
+    pub folder: String,
+    /// Start time (Unix timestamp)
+    pub started: i64,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// Outcome of a finished sync
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncResult {
+    pub device: String,
+    pub folder: String,
+    /// End time (Unix timestamp)
+    pub finished: i64,
+    pub uploaded: usize,
+    pub deleted: usize,
+    pub upload_bytes: u64,
+    pub delete_bytes: u64,
+    /// Error message of a failed sync
+    pub error: Option<String>,
+}
+
+/// Write one message as a JSON line
+pub fn send<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one message, `None` once the connection is closed
+pub fn receive<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<Option<T>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    let message = serde_json::from_str(&line).with_context(|| format!("Invalid message: {}", line.trim()))?;
+    Ok(Some(message))
+}
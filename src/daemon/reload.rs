@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/reload.rs
+
+//! Reload of the daemon configuration when config.yaml changes.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::daemon::state::DaemonState;
+use crate::sync::watcher::{start_watcher, WatcherControl};
+
+/// Editors write a file in several steps, wait for them to settle
+const CONFIG_DEBOUNCE_MS: u64 = 500;
+
+/// Reload the configuration whenever `path` changes. Its directory is watched,
+/// since many editors replace the file instead of writing into it.
+pub fn watch_config(state: DaemonState, path: PathBuf) -> Result<WatcherControl> {
+    let dir = path
+        .parent()
+        .map(Path::to_path_buf)
+        .with_context(|| format!("No directory for {}", path.display()))?;
+
+    let watcher = start_watcher(CONFIG_DEBOUNCE_MS, move |batch| {
+        if batch.iter().any(|ev| ev.paths.contains(&path)) {
+            // Errors are reported by reload_config
+            let _ = state.reload_config(&path);
+        }
+    })?;
+    watcher.add(dir);
+
+    Ok(watcher)
+}
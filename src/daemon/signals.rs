@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/signals.rs
+
+//! Unix signals of the daemon, handled one at a time on a dedicated thread.
+
+use anyhow::Result;
+use nix::sys::signal::SigSet;
+use std::thread;
+
+pub use nix::sys::signal::Signal;
+
+/// Block `signals` in the calling thread, and in every thread it starts
+/// afterwards, then pass them to `handler` from a dedicated thread.
+/// Must be called before any other thread is started.
+pub fn start_signal_handler(signals: &[Signal], handler: impl Fn(Signal) + Send + 'static) -> Result<()> {
+    let mut set = SigSet::empty();
+    for signal in signals {
+        set.add(*signal);
+    }
+    set.thread_block()?;
+
+    thread::spawn(move || loop {
+        match set.wait() {
+            Ok(signal) => handler(signal),
+            Err(e) => {
+                tracing::error!("Signal handling stopped: {}", e);
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
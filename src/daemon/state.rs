@@ -3,42 +3,70 @@
 
 //! State machine, handling device events and sync operations.
 
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use crate::core::task::SyncTask;
-use crate::context::ExecutionContext;
+use crate::config::AppConfig;
+use crate::context::{ExecutionContext, SharedContext};
+use crate::daemon::events::EventBus;
+use crate::daemon::protocol::{ActiveSync, DaemonStatus, Event, SyncProgress, SyncResult};
 use crate::sync::engine::SyncEngine;
+use crate::sync::hooks::{run_device_hooks, HookEvent};
 use crate::sync::watcher::WatcherControl;
 use crate::sync::planner::plan_sync;
-use notify::Event;
+use crate::utils::human::notify;
+use notify::Event as FsEvent;
 
 #[derive(Clone, Debug)]
 pub struct DaemonState {
-    ctx: Arc<ExecutionContext>,
+    ctx: SharedContext,
     engine: SyncEngine,
     watcher: WatcherControl,
     active_syncs: Arc<Mutex<Vec<SyncTask>>>,
+    paused: Arc<AtomicBool>,
+    /// Set once shutting down, no new sync starts
+    stopping: Arc<AtomicBool>,
+    /// Syncs requested while paused
+    deferred: Arc<Mutex<Vec<SyncTask>>>,
+    /// Last result of each folder
+    results: Arc<Mutex<Vec<SyncResult>>>,
+    /// Syncs run one after the other, whoever asks for them
+    queue: Arc<Mutex<()>>,
+    events: EventBus,
 }
 
 impl DaemonState {
-    pub fn new(ctx: ExecutionContext, engine: SyncEngine, watcher: WatcherControl) -> Self {
+    pub fn new(ctx: SharedContext, engine: SyncEngine, watcher: WatcherControl) -> Self {
         Self {
-            ctx: Arc::new(ctx),
+            ctx,
             engine,
             watcher,
             active_syncs: Arc::new(Mutex::new(Vec::new())),
+            paused: Arc::new(AtomicBool::new(false)),
+            stopping: Arc::new(AtomicBool::new(false)),
+            deferred: Arc::new(Mutex::new(Vec::new())),
+            results: Arc::new(Mutex::new(Vec::new())),
+            queue: Arc::new(Mutex::new(())),
+            events: EventBus::new(),
         }
     }
 
     /// Recompute the full sync plan and reconfigure watchers + active list.
-    fn refresh_plan(&self, reason: &str) {
+    /// Returns the new plan, empty when it could not be computed.
+    fn refresh_plan(&self, reason: &str) -> Vec<SyncTask> {
         tracing::info!("Daemon: refreshing sync plan ({})...", reason);
 
-        let plan = match plan_sync(&self.ctx) {
+        let plan = match plan_sync(&self.ctx.get()) {
             Ok(p) => p,
             Err(e) => {
                 tracing::error!("Plan sync error: {}", e);
-                return;
+                return vec![];
             }
         };
 
@@ -54,30 +82,98 @@ impl DaemonState {
             self.watcher.add(cfg.source.clone());
         }
 
-        tracing::info!("Daemon: {} active sync(s) after refresh.", plan.len());
-
-        // Initial full-sync on mount or config change
-        if !plan.is_empty() && reason == "device mounted" {
-            for cfg in plan {
-                if let Err(e) = self.engine.sync_config(&cfg) {
-                    tracing::error!("Initial sync error for '{}': {}", cfg.device, e);
-                }
-            }
+        // Deferred syncs follow the new plan
+        {
+            let mut deferred = self.deferred.lock().unwrap();
+            *deferred = plan
+                .iter()
+                .filter(|t| deferred.iter().any(|d| d.source == t.source && d.target == t.target))
+                .cloned()
+                .collect();
         }
+
+        tracing::info!("Daemon: {} active sync(s) after refresh.", plan.len());
+        plan
     }
 
     /// Called when a device is mounted.
-    pub fn on_device_mounted(&self, _device_name: &str) {
-        self.refresh_plan("device mounted");
+    pub fn on_device_mounted(&self, device_name: &str) {
+        self.events.publish(Event::DeviceMounted { device: device_name.to_string() });
+        self.run_device_hooks(device_name, HookEvent::Mount);
+
+        // Initial full-sync on mount
+        let plan = self.refresh_plan("device mounted");
+        if !plan.is_empty() {
+            self.run_syncs(plan);
+        }
     }
 
     /// Called when a device is unmounted.
-    pub fn on_device_unmounted(&self, _device_name: &str) {
+    pub fn on_device_unmounted(&self, device_name: &str) {
+        self.events.publish(Event::DeviceUnmounted { device: device_name.to_string() });
         self.refresh_plan("device unmounted");
+        self.run_device_hooks(device_name, HookEvent::Unmount);
+    }
+
+    /// Run the mount/unmount hooks of a configured device.
+    fn run_device_hooks(&self, device_name: &str, event: HookEvent) {
+        let ctx = self.ctx.get();
+        if let Some(dev) = ctx.config.devices.iter().find(|d| d.name == device_name) {
+            run_device_hooks(dev, &ctx.expand_mount(dev), event);
+        }
+    }
+
+    /// Load and check the configuration file again, then swap it in and refresh
+    /// the plan. An invalid file keeps the current configuration and is reported.
+    /// Folders whose settings changed are synced in the background: this runs
+    /// on the signal thread, which must stay free to handle SIGTERM.
+    /// Returns how many folders were queued.
+    pub fn reload_config(&self, path: &Path) -> Result<usize> {
+        let loaded = AppConfig::load_from(path).and_then(|config| {
+            config.load_tasks()?;
+            Ok(config)
+        });
+
+        let config = match loaded {
+            Ok(config) => config,
+            Err(e) => {
+                let message = format!("{:#}", e);
+                tracing::error!("Configuration not reloaded, keeping the current one: {}", message);
+                notify("Plainsync configuration error", &format!("{}\nThe previous configuration is kept.", message));
+                self.events.publish(Event::ConfigError { message });
+                return Err(e);
+            }
+        };
+
+        self.watcher.set_debounce(config.watch.debounce_ms);
+        self.ctx.replace(ExecutionContext::new(config));
+
+        tracing::info!("Configuration reloaded from {}.", path.display());
+        self.events.publish(Event::ConfigReloaded);
+
+        let before = self.active_syncs();
+        let changed: Vec<SyncTask> = self
+            .refresh_plan("config reloaded")
+            .into_iter()
+            .filter(|t| !before.iter().any(|b| b.same_sync(t)))
+            .collect();
+
+        let queued = changed.len();
+        if queued > 0 {
+            tracing::info!("Configuration changed {} folder(s), syncing them.", queued);
+            let state = self.clone();
+            thread::spawn(move || state.run_syncs(changed));
+        }
+        Ok(queued)
+    }
+
+    /// Current execution context
+    pub fn context(&self) -> Arc<ExecutionContext> {
+        self.ctx.get()
     }
 
     /// Called by watcher after event debounce.
-    pub fn handle_events(&self, events: Vec<Event>) {
+    pub fn handle_events(&self, events: Vec<FsEvent>) {
         let syncs = self.active_syncs();
 
         let mut impacted = Vec::new();
@@ -98,10 +194,197 @@ impl DaemonState {
 
         tracing::info!("Batch: triggering sync for {} folder(s)", impacted.len());
 
-        for cfg in impacted {
-            if let Err(e) = self.engine.sync_config(&cfg) {
-                tracing::error!("Sync error: {}", e);
+        self.run_syncs(impacted);
+    }
+
+    /// Sync tasks one after the other, recording and publishing their results.
+    /// While paused, tasks are kept for `resume` and nothing is returned.
+    pub fn run_syncs(&self, tasks: Vec<SyncTask>) -> Vec<SyncResult> {
+        if self.is_stopping() {
+            tracing::info!("Daemon stopping: {} sync(s) not started.", tasks.len());
+            return vec![];
+        }
+
+        if self.is_paused() {
+            let mut deferred = self.deferred.lock().unwrap();
+            for task in tasks {
+                if !deferred.iter().any(|t| t.source == task.source && t.target == task.target) {
+                    deferred.push(task);
+                }
+            }
+            tracing::info!("Daemon paused: {} sync(s) deferred.", deferred.len());
+            return vec![];
+        }
+
+        let _queue = self.queue.lock().unwrap();
+        let mut out = Vec::new();
+
+        for task in tasks {
+            if self.is_stopping() {
+                break;
+            }
+
+            let device = task.device.name.clone();
+            let folder = task.folder.target.clone();
+            self.events.publish(Event::SyncStarted { device: device.clone(), folder: folder.clone() });
+
+            let outcome = self.engine.sync_config(&task);
+            if let Err(e) = &outcome {
+                tracing::error!("Sync error for '{}': {:#}", device, e);
+            }
+            let stats = match &outcome {
+                Ok(Some(stats)) => stats.clone(),
+                Ok(None) => continue,
+                Err(_) => Default::default(),
+            };
+
+            let result = SyncResult {
+                device,
+                folder,
+                finished: chrono::Utc::now().timestamp(),
+                uploaded: stats.upload_count,
+                deleted: stats.delete_count,
+                upload_bytes: stats.upload_bytes,
+                delete_bytes: stats.delete_bytes,
+                error: outcome.err().map(|e| format!("{:#}", e)),
+            };
+
+            {
+                let mut results = self.results.lock().unwrap();
+                results.retain(|r| r.device != result.device || r.folder != result.folder);
+                results.push(result.clone());
             }
+            self.events.publish(Event::SyncFinished(result.clone()));
+            out.push(result);
+        }
+
+        out
+    }
+
+    /// Active tasks of a device and/or folder target (all of them without filter)
+    pub fn select(&self, device: Option<&str>, folder: Option<&str>) -> Result<Vec<SyncTask>> {
+        let tasks: Vec<SyncTask> = self
+            .active_syncs()
+            .into_iter()
+            .filter(|t| device.is_none_or(|d| t.device.name == d))
+            .filter(|t| folder.is_none_or(|f| t.folder.target == f))
+            .collect();
+
+        if tasks.is_empty() {
+            anyhow::bail!("No active sync matches (is the device mounted?)");
+        }
+        Ok(tasks)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stop syncing; changes are kept until `resume`
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::SeqCst) {
+            tracing::info!("Daemon paused.");
+            self.events.publish(Event::Paused);
+        }
+    }
+
+    /// Sync in the background what changed while paused
+    pub fn resume(&self) {
+        if !self.paused.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        tracing::info!("Daemon resumed.");
+        self.events.publish(Event::Resumed);
+
+        let deferred = std::mem::take(&mut *self.deferred.lock().unwrap());
+        if !deferred.is_empty() {
+            let state = self.clone();
+            thread::spawn(move || state.run_syncs(deferred));
+        }
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Stop for good: refuse new syncs, let the sync in progress finish its
+    /// current file and checkpoint its index, then stop watching sources.
+    pub fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.engine.stop();
+
+        // Syncs in progress hold the queue until they are done
+        drop(self.queue.lock().unwrap());
+
+        self.watcher.stop();
+        tracing::info!("Daemon: all syncs stopped.");
+    }
+
+    /// Receive daemon events from now on
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Snapshot for the control socket
+    pub fn status(&self) -> DaemonStatus {
+        let active = self
+            .active_syncs()
+            .iter()
+            .map(|t| ActiveSync {
+                device: t.device.name.clone(),
+                folder: t.folder.target.clone(),
+                source: t.source.display().to_string(),
+                target: t.target.display().to_string(),
+            })
+            .collect();
+
+        let running = self.engine.running().map(|r| SyncProgress {
+            device: r.device,
+            folder: r.folder,
+            started: r.started,
+            done: r.bar.position(),
+            total: r.bar.length().unwrap_or(0),
+        });
+
+        DaemonStatus {
+            paused: self.is_paused(),
+            active,
+            running,
+            deferred: self.deferred.lock().unwrap().len(),
+            results: self.results.lock().unwrap().clone(),
+        }
+    }
+
+    /// Time since the sync in progress last did something, if one is running
+    pub fn sync_idle(&self) -> Option<Duration> {
+        self.engine.running().map(|r| r.activity.age())
+    }
+
+    /// One-line summary, e.g. "Syncing Y1: 120/450 files" or "Idle, 2 devices mounted"
+    pub fn status_line(&self) -> String {
+        if self.is_stopping() {
+            return "Stopping, finishing the current file".to_string();
+        }
+
+        if let Some(r) = self.engine.running() {
+            return match r.bar.length().unwrap_or(0) {
+                0 => format!("Syncing {}: checking {}", r.device, r.folder),
+                total => format!("Syncing {}: {}/{} files", r.device, r.bar.position(), total),
+            };
+        }
+
+        let mut devices: Vec<String> = self.active_syncs().into_iter().map(|t| t.device.name).collect();
+        devices.sort();
+        devices.dedup();
+        let mounted = match devices.len() {
+            1 => "1 device mounted".to_string(),
+            n => format!("{} devices mounted", n),
+        };
+
+        if self.is_paused() {
+            format!("Paused, {}", mounted)
+        } else {
+            format!("Idle, {}", mounted)
         }
     }
 
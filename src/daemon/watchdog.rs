@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MIT
+// src/daemon/watchdog.rs
+
+//! systemd reporting: `STATUS=` kept current with the daemon state, and
+//! `WATCHDOG=1` sent only while every daemon loop is alive, so that a hung
+//! loop (blocked copy on a dead device…) gets the service restarted.
+
+use sd_notify::NotifyState;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::daemon::state::DaemonState;
+use crate::utils::heartbeat::Heartbeat;
+
+/// Longest delay between two status updates
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Loop that has not beaten for `timeout`, if any. A loop busy with a sync
+/// is alive as long as the sync moves on (`sync_idle`: time since it last did,
+/// a file done or a pulse from within one).
+pub fn stalled_loop(loops: &[(&'static str, Heartbeat)], timeout: Duration, sync_idle: Option<Duration>) -> Option<&'static str> {
+    if sync_idle.is_some_and(|idle| idle < timeout) {
+        return None;
+    }
+    loops.iter().find(|(_, hb)| hb.age() >= timeout).map(|(name, _)| *name)
+}
+
+/// Report to systemd until the daemon stops. Keepalives are only sent when
+/// the service has a `WatchdogSec`.
+pub fn start_watchdog(state: DaemonState, loops: Vec<(&'static str, Heartbeat)>) -> JoinHandle<()> {
+    let mut usec = 0;
+    let timeout = sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec));
+    let tick = timeout.map_or(STATUS_INTERVAL, |t| (t / 2).min(STATUS_INTERVAL));
+
+    if let Some(timeout) = timeout {
+        tracing::info!("systemd watchdog enabled ({:?}).", timeout);
+    }
+
+    thread::spawn(move || {
+        let mut status = String::new();
+        let mut stalled = None;
+        // Last progress seen for the sync in progress, and when it changed
+        let mut progress = None;
+        let mut progressed = Instant::now();
+
+        while !state.is_stopping() {
+            let line = state.status_line();
+            if line != status {
+                sd_notify::notify(false, &[NotifyState::Status(&line)]).ok();
+                status = line;
+            }
+
+            if let Some(timeout) = timeout {
+                // Files done, or work within a file: a large copy, a transcode, a scan
+                let running = state.status().running.map(|r| (r.device, r.folder, r.started, r.done));
+                if running != progress {
+                    progress = running;
+                    progressed = Instant::now();
+                }
+                let sync_idle = state.sync_idle().map(|idle| idle.min(progressed.elapsed()));
+
+                let now_stalled = stalled_loop(&loops, timeout, sync_idle);
+                match now_stalled {
+                    None => {
+                        sd_notify::notify(false, &[NotifyState::Watchdog]).ok();
+                    }
+                    Some(name) if stalled != now_stalled => {
+                        tracing::error!("Daemon {} loop stuck for {:?}, letting the watchdog restart the service.", name, timeout);
+                    }
+                    Some(_) => {}
+                }
+                stalled = now_stalled;
+            }
+
+            thread::sleep(tick);
+        }
+
+        sd_notify::notify(false, &[NotifyState::Status(&state.status_line())]).ok();
+    })
+}
@@ -9,4 +9,5 @@ pub mod core;
 pub mod daemon;
 pub mod sync;
 pub mod utils;
-pub mod context;
\ No newline at end of file
+pub mod context;
+pub mod profiles;
\ No newline at end of file
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: MIT
+// src/profiles.rs
+
+//! Built-in device profiles: presets for common players (mount template,
+//! filesystem rules, formats, playlist conventions) merged under the
+//! device configuration, which overrides any of their fields.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use serde_yaml::{Mapping, Value};
+
+use crate::config::DeviceConfig;
+
+/// Bundled profiles: name, description, preset
+pub const PROFILES: &[(&str, &str, &str)] = &[
+    ("y1", "Innoasis Y1", include_str!("../assets/profiles/y1.yaml")),
+    ("rockbox", "Rockbox player", include_str!("../assets/profiles/rockbox.yaml")),
+    ("android-sd", "Android SD card", include_str!("../assets/profiles/android-sd.yaml")),
+    ("kindle", "Kindle e-reader", include_str!("../assets/profiles/kindle.yaml")),
+    ("usb-backup", "USB backup drive", include_str!("../assets/profiles/usb-backup.yaml")),
+];
+
+/// Preset of a profile, by name
+pub fn preset(name: &str) -> Result<Value> {
+    let (_, _, yaml) = PROFILES.iter().find(|(n, _, _)| n.eq_ignore_ascii_case(name)).with_context(|| {
+        let names: Vec<&str> = PROFILES.iter().map(|(n, _, _)| *n).collect();
+        format!("Unknown device profile {:?} (available: {})", name, names.join(", "))
+    })?;
+
+    serde_yaml::from_str(yaml).with_context(|| format!("Invalid bundled profile {}", name))
+}
+
+/// Deep merge: mappings are merged key by key, anything else in `over` wins
+pub fn merge(base: Value, over: Value) -> Value {
+    match (base, over) {
+        (Value::Mapping(mut base), Value::Mapping(over)) => {
+            for (key, value) in over {
+                let merged = match base.remove(&key) {
+                    Some(old) => merge(old, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (_, over) => over,
+    }
+}
+
+/// Device YAML with its profile applied. The preset `playlists` conventions
+/// only fill folders that enable playlists.
+pub fn apply_profile(device: Value) -> Result<Value> {
+    let Some(name) = device.get("profile").and_then(Value::as_str) else { return Ok(device) };
+
+    let mut preset = preset(name)?;
+    let conventions = preset.as_mapping_mut().and_then(|m| m.remove("playlists"));
+
+    let mut merged = merge(preset, device);
+
+    if let (Some(conventions), Some(folders)) = (conventions, merged.get_mut("folders").and_then(Value::as_sequence_mut)) {
+        for playlists in folders.iter_mut().filter_map(|f| f.get_mut("playlists")) {
+            let own = match std::mem::take(playlists) {
+                Value::Null => Value::Mapping(Mapping::new()),
+                other => other,
+            };
+            *playlists = merge(conventions.clone(), own);
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Deserialize the device list, applying profiles first
+pub fn deserialize_devices<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<DeviceConfig>, D::Error> {
+    use serde::de::Error;
+
+    let devices: Option<Vec<Value>> = Option::deserialize(deserializer)?;
+
+    devices
+        .unwrap_or_default()
+        .into_iter()
+        .map(|device| {
+            let explicit_formats = device.get("formats").is_some();
+            let device = apply_profile(device).map_err(|e| D::Error::custom(format!("{:#}", e)))?;
+            let mut device: DeviceConfig = serde_yaml::from_value(device).map_err(D::Error::custom)?;
+            device.formats_from_profile = device.formats.is_some() && !explicit_formats;
+            Ok(device)
+        })
+        .collect()
+}
@@ -5,11 +5,14 @@
 //! Calls the provided callbacks whenever a device is mounted or unmounted.
 
 use std::collections::HashSet;
-use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::context::ExecutionContext;
+use crate::context::SharedContext;
 use crate::sync::detect::detect_all_devices;
+use crate::utils::heartbeat::Heartbeat;
 
 pub struct DetectCallbacks<CbMount, CbUmount>
 where
@@ -20,18 +23,47 @@ where
     pub on_unmount: CbUmount,
 }
 
+/// Handle of the detection thread
+pub struct DetectControl {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+    heartbeat: Heartbeat,
+}
+
+impl DetectControl {
+    /// Liveness of the detection thread, stale while a callback runs
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// End the loop and wait for its thread
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.thread.join();
+    }
+}
+
+/// Start the detection thread. The context is read again on every pass,
+/// so devices added to or removed from a reloaded configuration are followed.
 pub fn start_detect_loop<CbMount, CbUmount>(
-    ctx: ExecutionContext,
+    ctx: SharedContext,
     callbacks: DetectCallbacks<CbMount, CbUmount>,
-) where
+) -> DetectControl
+where
     CbMount: Fn(String) + Send + Sync + 'static,
     CbUmount: Fn(String) + Send + Sync + 'static,
 {
-    thread::spawn(move || {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let heartbeat = Heartbeat::new();
+    let beat = heartbeat.clone();
+
+    let thread = thread::spawn(move || {
         let mut previous: HashSet<String> = HashSet::new();
 
-        loop {
-            let detected: HashSet<String> = detect_all_devices(&ctx)
+        while !stopped.load(Ordering::SeqCst) {
+            beat.beat();
+            let detected: HashSet<String> = detect_all_devices(&ctx.get())
                 .into_iter()
                 .map(|(dev, _)| dev.name)
                 .collect();
@@ -51,4 +83,6 @@ pub fn start_detect_loop<CbMount, CbUmount>(
             thread::sleep(Duration::from_secs(1));
         }
     });
+
+    DetectControl { stop, thread, heartbeat }
 }
@@ -5,35 +5,69 @@
 
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
+use indicatif::ProgressBar;
 use notify::Event;
 
 use crate::core::task::SyncTask;
-use crate::sync::run::sync_folder;
+use crate::sync::operations::Progress;
+use crate::sync::run::sync_folder_with;
+use crate::utils::fs::StopSignal;
+use crate::utils::heartbeat::Heartbeat;
+use crate::utils::human::SyncStats;
+
+/// Sync being executed, with its hidden progress bar.
+#[derive(Debug, Clone)]
+pub struct RunningSync {
+    pub device: String,
+    pub folder: String,
+    pub started: i64,
+    pub bar: ProgressBar,
+    /// Pulsed while the sync works, within a file too (copy chunks, scan…)
+    pub activity: Heartbeat,
+}
 
 /// Stateless sync engine: it only executes syncs.
 #[derive(Debug, Clone)]
 pub struct SyncEngine {
-    syncing: Arc<Mutex<bool>>,
+    running: Arc<Mutex<Option<RunningSync>>>,
+    stop: StopSignal,
+}
+
+impl Default for SyncEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SyncEngine {
     pub fn new() -> Self {
         Self {
-            syncing: Arc::new(Mutex::new(false)),
+            running: Arc::new(Mutex::new(None)),
+            stop: StopSignal::new(),
         }
     }
 
     /// Run a synchronization for an existing SyncTask.
-    pub fn sync_config(&self, task: &SyncTask) -> Result<()> {
-        let mut lock = self.syncing.lock().unwrap();
-        if *lock {
+    /// Returns `None` when skipped because another sync is in progress,
+    /// or when stopped before the end.
+    pub fn sync_config(&self, task: &SyncTask) -> Result<Option<SyncStats>> {
+        let mut lock = self.running.lock().unwrap();
+        if lock.is_some() {
             tracing::warn!(
                 "Sync already in progress for device '{}' — skipped.",
                 task.device
             );
-            return Ok(());
+            return Ok(None);
         }
-        *lock = true;
+        let bar = ProgressBar::hidden();
+        let activity = Heartbeat::new();
+        *lock = Some(RunningSync {
+            device: task.device.name.clone(),
+            folder: task.folder.target.clone(),
+            started: chrono::Utc::now().timestamp(),
+            bar: bar.clone(),
+            activity: activity.clone(),
+        });
         drop(lock);
 
         tracing::info!(
@@ -42,14 +76,23 @@ impl SyncEngine {
             task.target.display()
         );
 
-        let result = sync_folder(task, false, false);
+        let result = activity.around(|| sync_folder_with(task, false, &Progress::Track(bar), &self.stop));
 
-        let mut lock = self.syncing.lock().unwrap();
-        *lock = false;
+        *self.running.lock().unwrap() = None;
 
         result
     }
 
+    /// Stop for good: the sync in progress ends after its current file.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Sync in progress, if any
+    pub fn running(&self) -> Option<RunningSync> {
+        self.running.lock().unwrap().clone()
+    }
+
     /// Match FS events to active SyncTasks.
     /// Used by DaemonState for watcher batch processing.
     pub fn match_event<'a>(
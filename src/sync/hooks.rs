@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MIT
+// src/sync/hooks.rs
+
+//! User-defined shell hooks run at device and folder lifecycle events.
+//! Device hooks run before folder hooks; context is passed in
+//! `PLAINSYNC_*` environment variables.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::{DeviceConfig, FolderConfig, HooksConfig};
+use crate::core::task::SyncTask;
+use crate::utils::human::SyncStats;
+
+/// Lifecycle event triggering a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Mount,
+    PreSync,
+    PostSync,
+    Error,
+    Unmount,
+}
+
+impl HookEvent {
+    /// Name of the event, as used in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Mount => "on_mount",
+            HookEvent::PreSync => "pre_sync",
+            HookEvent::PostSync => "post_sync",
+            HookEvent::Error => "on_error",
+            HookEvent::Unmount => "on_unmount",
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a HooksConfig) -> Option<&'a str> {
+        match self {
+            HookEvent::Mount => hooks.on_mount.as_deref(),
+            HookEvent::PreSync => hooks.pre_sync.as_deref(),
+            HookEvent::PostSync => hooks.post_sync.as_deref(),
+            HookEvent::Error => hooks.on_error.as_deref(),
+            HookEvent::Unmount => hooks.on_unmount.as_deref(),
+        }
+    }
+}
+
+/// Run the hooks of a device and all its folders for a mount/unmount event.
+/// Failures are logged, they never prevent the event from being handled.
+pub fn run_device_hooks(device: &DeviceConfig, mount: &Path, event: HookEvent) {
+    let base = device_env(device, mount, event);
+
+    if let Err(e) = run_hook(event.command(&device.hooks), &base) {
+        tracing::warn!("{} hook failed for {}: {:#}", event.name(), device.name, e);
+    }
+
+    for folder in &device.folders {
+        let mut env = base.clone();
+        env.extend(folder_env(folder));
+        if let Err(e) = run_hook(event.command(&folder.hooks), &env) {
+            tracing::warn!("{} hook failed for {}/{}: {:#}", event.name(), device.name, folder.target, e);
+        }
+    }
+}
+
+/// Run the device then folder hooks of a task for a sync event.
+/// `stats` is set after a sync, `error` when it failed.
+pub fn run_task_hooks(task: &SyncTask, event: HookEvent, stats: Option<&SyncStats>, error: Option<&str>) -> Result<()> {
+    let mut env = device_env(&task.device, &task.mount, event);
+    env.extend(folder_env(&task.folder));
+    env.push(("PLAINSYNC_SOURCE".into(), task.source.display().to_string()));
+    env.push(("PLAINSYNC_TARGET".into(), task.target.display().to_string()));
+    env.push(("PLAINSYNC_MODE".into(), format!("{:?}", task.mode)));
+
+    if let Some(stats) = stats {
+        env.push(("PLAINSYNC_UPLOADS".into(), stats.upload_count.to_string()));
+        env.push(("PLAINSYNC_DELETES".into(), stats.delete_count.to_string()));
+        env.push(("PLAINSYNC_UPLOAD_BYTES".into(), stats.upload_bytes.to_string()));
+        env.push(("PLAINSYNC_DELETE_BYTES".into(), stats.delete_bytes.to_string()));
+    }
+    if matches!(event, HookEvent::PostSync | HookEvent::Error) {
+        let status = if error.is_some() { "1" } else { "0" };
+        env.push(("PLAINSYNC_EXIT_STATUS".into(), status.into()));
+    }
+    if let Some(error) = error {
+        env.push(("PLAINSYNC_ERROR".into(), error.to_string()));
+    }
+
+    run_hook(event.command(&task.device.hooks), &env)?;
+    run_hook(event.command(&task.folder.hooks), &env)
+}
+
+fn device_env(device: &DeviceConfig, mount: &Path, event: HookEvent) -> Vec<(String, String)> {
+    vec![
+        ("PLAINSYNC_EVENT".into(), event.name().into()),
+        ("PLAINSYNC_DEVICE".into(), device.name.clone()),
+        ("PLAINSYNC_DEVICE_LABEL".into(), device.label.clone().unwrap_or_default()),
+        ("PLAINSYNC_MOUNT".into(), mount.display().to_string()),
+    ]
+}
+
+fn folder_env(folder: &FolderConfig) -> Vec<(String, String)> {
+    vec![("PLAINSYNC_FOLDER".into(), folder.target.clone())]
+}
+
+/// Run one hook command through `sh -c`. A non-zero exit is an error.
+fn run_hook(command: Option<&str>, env: &[(String, String)]) -> Result<()> {
+    let Some(command) = command else { return Ok(()) };
+
+    tracing::debug!("Running hook: {}", command);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run hook: {}", command))?;
+
+    if !status.success() {
+        anyhow::bail!("Hook `{}` exited with {}", command, status);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: MIT
+// src/sync/import.rs
+
+//! Import mode: copy new files from a device (camera, recorder) into a
+//! date-based layout on the host, deduplicated by content hash.
+//! Move mode: same, then delete originals from the device once each copy
+//! has been verified.
+
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::config::{SyncMode, DEFAULT_IMPORT_TEMPLATE, DEFAULT_MOVE_TEMPLATE};
+use crate::core::diff::compute_diff;
+use crate::core::index::{Index, IndexedFile};
+use crate::core::journal::{Journal, JournalEntry};
+use crate::core::scanner::scan_local_with;
+use crate::core::task::SyncTask;
+use crate::sync::operations::Progress;
+use crate::sync::run::interrupted;
+use crate::sync::validation::validate_sync_paths;
+use crate::utils::fs::{copy_file, delete_one, hash_file, remove_temp_files, CopyOptions, StopSignal};
+use crate::utils::human::{human_size, notify, SyncStats};
+
+const TEST_WRITE: bool = true;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Render an import template for a device file.
+/// Placeholders: {year} {month} {day} {hour} {minute} {second} (from mtime),
+/// {name} {stem} {ext} {dir} (from the path on the device).
+pub fn render_template(template: &str, file: &IndexedFile) -> PathBuf {
+    let rel = Path::new(&file.path);
+    let dt = Local
+        .timestamp_opt(file.mtime, 0)
+        .single()
+        .unwrap_or_else(|| Local.timestamp_opt(0, 0).unwrap());
+
+    let text = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = rel.parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let replacements = [
+        ("{year}", dt.format("%Y").to_string()),
+        ("{month}", dt.format("%m").to_string()),
+        ("{day}", dt.format("%d").to_string()),
+        ("{hour}", dt.format("%H").to_string()),
+        ("{minute}", dt.format("%M").to_string()),
+        ("{second}", dt.format("%S").to_string()),
+        ("{name}", text(rel.file_name())),
+        ("{stem}", text(rel.file_stem())),
+        ("{ext}", text(rel.extension())),
+        ("{dir}", dir),
+    ];
+
+    let mut out = template.to_string();
+    for (key, val) in replacements {
+        out = out.replace(key, &val);
+    }
+
+    // Empty components (e.g. {dir} at the root) would produce "//"
+    out.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+/// Pick a destination that does not overwrite an unrelated file:
+/// `name.ext`, then `name (1).ext`, `name (2).ext`…
+/// The flag is set when a file with the same content is already there.
+fn free_destination(target: &Path, rel: &Path, hash: &str) -> Result<(PathBuf, bool)> {
+    let stem = rel.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = rel.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    let mut candidate = rel.to_path_buf();
+    let mut n = 1;
+
+    while target.join(&candidate).exists() {
+        if hash_file(&target.join(&candidate))? == hash {
+            return Ok((candidate, true));
+        }
+        candidate = rel.with_file_name(format!("{} ({}){}", stem, n, ext));
+        n += 1;
+    }
+
+    Ok((candidate, false))
+}
+
+/// Check that a copy has the expected size and content hash
+fn verify_copy(path: &Path, file: &IndexedFile, hash: &str) -> Result<bool> {
+    Ok(path.metadata()?.len() == file.size && hash_file(path)? == hash)
+}
+
+/// Whether the host still holds an identical copy of a device file
+fn host_copy_matches(task: &SyncTask, idx: &Index, file: &IndexedFile) -> Result<bool> {
+    let hash = hash_file(&task.source.join(&file.path))?;
+    match idx.imported_as(&hash) {
+        Some(stored) => {
+            let host = task.target.join(stored);
+            Ok(host.is_file() && hash_file(&host)? == hash)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Delete device originals that are safely on the host and old enough.
+/// Files copied and verified by this run (`verified`) go right away; the
+/// others (duplicates, imports of earlier runs) only once their host copy
+/// is checked again.
+fn delete_moved(
+    task: &SyncTask,
+    handled: &mut Vec<IndexedFile>,
+    verified: &HashSet<String>,
+    idx: &Index,
+    journal: &mut Journal,
+    verbose: bool,
+) -> Result<usize> {
+    let min_age = task.folder.delete_after_days.unwrap_or(0) as i64 * SECS_PER_DAY;
+    let now = chrono::Utc::now().timestamp();
+
+    let (due, kept): (Vec<_>, Vec<_>) = handled
+        .drain(..)
+        .partition(|f| now - f.mtime >= min_age);
+    *handled = kept;
+
+    let mut deleted = 0;
+    for file in due {
+        if !verified.contains(&file.path) && !host_copy_matches(task, idx, &file)? {
+            tracing::warn!("Host copy of {} is missing or changed, keeping it on the device.", file.path);
+            handled.push(file);
+            continue;
+        }
+
+        if verbose {
+            tracing::info!("DELETE {} (from device)", file.path);
+        }
+        delete_one(&task.source, &PathBuf::from(&file.path))?;
+        journal.record(&JournalEntry::Delete { path: file.path.clone() })?;
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Import new device files for one Import or Move task, `None` when stopped
+pub fn import_folder(task: &SyncTask, verbose: bool, progress: &Progress, stop: &StopSignal) -> Result<Option<SyncStats>> {
+    let start = Instant::now();
+    let src = &task.source;
+    let dst = &task.target;
+    let moving = matches!(task.mode, SyncMode::Move);
+    let default_template = if moving { DEFAULT_MOVE_TEMPLATE } else { DEFAULT_IMPORT_TEMPLATE };
+    let template = task.folder.template.as_deref().unwrap_or(default_template);
+
+    validate_sync_paths(src, dst, TEST_WRITE)?;
+
+    let mut idx = task.load_index()?;
+
+    let mut journal = Journal::for_index(&task.index_path);
+    remove_temp_files(dst, &journal.in_progress()?)?;
+    let resumed = journal.replay(&mut idx)?;
+    if resumed > 0 {
+        println!("Resuming interrupted import for {}: {} file(s) already done.", task.device, resumed);
+    }
+
+    // Only files new or changed on the device since the last run are candidates
+    let mut device_files = scan_local_with(src, task.folder.symlinks)?;
+    let candidates = compute_diff(&device_files, &idx.files).to_upload;
+
+    if candidates.is_empty() {
+        let mut deleted = 0;
+        if moving {
+            deleted = delete_moved(task, &mut device_files, &HashSet::new(), &idx, &mut journal, verbose)?;
+            if deleted > 0 {
+                println!("Deleted {} moved file(s) from {}.", deleted, task.device);
+            }
+        }
+        idx.update(device_files)?;
+        journal.clear()?;
+        println!("Nothing to import from {}.", task.device);
+        return Ok(Some(SyncStats::new(0, deleted, 0, 0)));
+    }
+
+    println!("Importing from {} into {}.", src.display(), dst.display());
+    notify(
+        &format!("Import started for {}", task.device),
+        &format!("{} new file(s) on device", candidates.len()),
+    );
+
+    let pb = progress.start(candidates.len() as u64);
+
+    let mut copy = CopyOptions { stop: stop.clone(), ..CopyOptions::new(task.max_bytes_per_sec, task.folder.preserve.clone()) };
+    let (mut imported, mut skipped, mut bytes) = (0usize, 0usize, 0u64);
+    let mut failed: HashSet<String> = HashSet::new();
+    let mut verified: HashSet<String> = HashSet::new();
+
+    for file in &candidates {
+        if copy.stop.is_stopped() {
+            return interrupted(task, &mut journal);
+        }
+
+        if let Some(pb) = &pb {
+            pb.set_message(format!("import {}", file.path));
+            pb.inc(1);
+        }
+
+        let src_path = src.join(&file.path);
+        let hash = hash_file(&src_path)?;
+
+        let (rel, duplicate) = match idx.imported_as(&hash) {
+            Some(stored) => (PathBuf::from(stored), true),
+            None => free_destination(dst, &render_template(template, file), &hash)?,
+        };
+
+        if duplicate {
+            if verbose {
+                tracing::info!("SKIP {} (already imported)", file.path);
+            }
+            skipped += 1;
+        } else {
+            if verbose {
+                tracing::info!("IMPORT {} → {}", file.path, rel.display());
+            }
+            let dst_path = dst.join(&rel);
+            journal.record(&JournalEntry::Copying { target: rel.to_string_lossy().into_owned() })?;
+            copy_file(&src_path, &dst_path, &mut copy)?;
+
+            // Originals are only deleted later if the copy is proven identical
+            if moving {
+                if !verify_copy(&dst_path, file, &hash)? {
+                    tracing::error!("Verification failed for {}, keeping it on the device.", file.path);
+                    let _ = std::fs::remove_file(&dst_path);
+                    failed.insert(file.path.clone());
+                    continue;
+                }
+                verified.insert(file.path.clone());
+            }
+
+            imported += 1;
+            bytes += file.size;
+        }
+
+        let stored = rel.to_string_lossy().into_owned();
+        journal.record(&JournalEntry::Import { file: file.clone(), hash: hash.clone(), stored: stored.clone() })?;
+        idx.set_imported(&hash, stored);
+    }
+
+    if let Some(pb) = pb { pb.finish(); }
+
+    // Failed files stay candidates for the next run
+    device_files.retain(|f| !failed.contains(&f.path));
+
+    let deleted = if moving {
+        delete_moved(task, &mut device_files, &verified, &idx, &mut journal, verbose)?
+    } else {
+        0
+    };
+
+    idx.update(device_files)?;
+    journal.clear()?;
+
+    if !failed.is_empty() {
+        notify(
+            &format!("Verification failed for {}", task.device),
+            &format!("{} file(s) could not be verified and were left on the device", failed.len()),
+        );
+    }
+
+    let elapsed = start.elapsed();
+    println!("Completed in {:.2?}.", elapsed);
+
+    notify(
+        &format!("Import completed for {}", task.device),
+        &format!(
+            "{} imported ({}), {} already imported, {} deleted from device, in {:.2?}",
+            imported,
+            human_size(bytes),
+            skipped,
+            deleted,
+            elapsed
+        ),
+    );
+
+    Ok(Some(SyncStats::new(imported, deleted, bytes, 0)))
+}
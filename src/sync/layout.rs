@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: MIT
+// src/sync/layout.rs
+
+//! Tag-driven target layout: the device path of a track is computed from
+//! its tags (`{albumartist}/{album}/{track:02} {title}.{ext}`) instead of
+//! mirroring the source. Placements are stored in the index, so a tag edit
+//! moves the file on the device instead of deleting and copying it.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{FilesystemRules, SyncMode, TranscodeRule};
+use crate::core::index::{Index, IndexedFile};
+use crate::core::journal::{Journal, JournalEntry};
+use crate::core::tags::{is_tagged_file, Tags};
+use crate::core::task::SyncTask;
+use crate::sync::rewrite;
+use crate::sync::transcode::record_for;
+use crate::utils::fs::{create_parents, delete_one};
+
+/// Value used for tags missing from a file
+const UNKNOWN: &str = "Unknown";
+
+/// Characters not allowed in file names on common device filesystems (FAT, exFAT)
+const FORBIDDEN: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Path of a source file on the target without layout: its own path,
+/// with the output extension when it is transcoded.
+pub fn natural_target(rules: &[TranscodeRule], path: &str) -> String {
+    record_for(rules, path).map_or_else(|| path.to_string(), |r| r.output)
+}
+
+/// Tag value made safe for a path component
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if FORBIDDEN.contains(&c) || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// Name cut to `max` bytes, keeping its extension
+fn shorten(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    };
+    let mut end = max.saturating_sub(ext.len()).min(stem.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), ext)
+}
+
+/// Make a target path acceptable to the device filesystem
+pub fn fit_filesystem(rules: &FilesystemRules, path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let mut name = segment.to_string();
+            if rules.portable_names {
+                name = sanitize(&name).trim_end_matches(['.', ' ']).to_string();
+            }
+            match rules.max_name {
+                Some(max) => shorten(&name, max),
+                None => name,
+            }
+        })
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Value of one `{key}` or `{key:0N}` placeholder
+fn placeholder(token: &str, tags: &Tags, source: &str, ext: &str) -> String {
+    let (key, width) = match token.split_once(':') {
+        Some((key, width)) => (key, width.parse::<usize>().ok()),
+        None => (token, None),
+    };
+
+    let stem = Path::new(source).file_stem().map(|s| s.to_string_lossy().into_owned());
+    let key = key.trim().to_lowercase();
+
+    let value = match key.as_str() {
+        "ext" => Some(ext.to_string()),
+        "filename" => stem,
+        "albumartist" => tags.get("albumartist").or_else(|| tags.get("artist")).map(str::to_string),
+        "title" => tags.get("title").map(str::to_string).or(stem),
+        key => tags.get(key).map(str::to_string),
+    };
+    let value = value.unwrap_or_else(|| UNKNOWN.to_string());
+
+    // Zero-padded numbers: "3/12" with {track:02} gives "03"
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    match (width, digits.parse::<u64>()) {
+        (Some(width), Ok(n)) => format!("{:0width$}", n, width = width),
+        _ => sanitize(&value),
+    }
+}
+
+/// Render a layout template for a track. `ext` is the extension on the target.
+/// Placeholders: any tag ({artist}, {album}, {year}, {genre}, {disc}…),
+/// {albumartist} (falls back to the artist), {title} (falls back to the file name),
+/// {filename}, {ext}. Numbers take a width: {track:02}.
+pub fn render_layout(template: &str, tags: &Tags, source: &str, ext: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { break };
+        out.push_str(&rest[..start]);
+        out.push_str(&placeholder(&rest[start + 1..start + len], tags, source, ext));
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+
+    // Devices reject trailing dots and spaces; ".." must not escape the target
+    out.split('/')
+        .map(|c| c.trim().trim_end_matches('.').trim_end())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `dir/name (n).ext`
+fn numbered(path: &str, n: usize) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Target paths already given out, with the source file holding each.
+struct Taken<'a> {
+    /// The device ignores case (FAT)
+    fold: bool,
+    exact: HashMap<String, &'a str>,
+    /// Lowercased path, source and whether plainsync changed the path
+    folded: HashMap<String, (&'a str, bool)>,
+}
+
+impl<'a> Taken<'a> {
+    fn new(fold: bool) -> Self {
+        Self { fold, exact: HashMap::new(), folded: HashMap::new() }
+    }
+
+    /// Source already stored at `path`. Paths differing only in case collide when the
+    /// device ignores case and one of them was changed: unchanged host paths differing
+    /// in case already coexist on the host and are left alone.
+    fn get(&self, path: &str, changed: bool) -> Option<&'a str> {
+        if let Some(other) = self.exact.get(path) {
+            return Some(other);
+        }
+        if !self.fold {
+            return None;
+        }
+        self.folded
+            .get(&path.to_lowercase())
+            .filter(|(_, other_changed)| changed || *other_changed)
+            .map(|(other, _)| *other)
+    }
+
+    fn insert(&mut self, path: &str, source: &'a str, changed: bool) {
+        self.exact.insert(path.to_string(), source);
+        if self.fold {
+            self.folded.entry(path.to_lowercase()).or_insert((source, changed));
+        }
+    }
+}
+
+/// Target path of every source file. Tagged tracks follow the layout template
+/// when there is one; other files (covers, untagged tracks) keep their natural path.
+/// Rewrite rules, `max_depth` and the device filesystem rules then apply to every path.
+/// Laid out tracks sharing a path get a " (2)", " (3)"… suffix; other collisions and
+/// paths over the device limit are an error, raised before any write. Case is ignored
+/// on devices with `portable_names` (FAT), for paths plainsync changed.
+/// Expects a refreshed tag cache.
+pub fn plan_targets(task: &SyncTask, files: &[IndexedFile], idx: &Index) -> Result<BTreeMap<String, String>> {
+    let folder = &task.folder;
+    // Filesystem rules describe the device, they do not apply to pulls onto the host
+    let host = FilesystemRules::default();
+    let fs_rules = if matches!(task.mode, SyncMode::Push) { &task.device.filesystem } else { &host };
+    let layout = folder.layout.as_deref();
+    let rules = &folder.transcode;
+    let max_depth = folder.max_depth.or(fs_rules.max_depth);
+    let finish = |path: &str| fit_filesystem(fs_rules, &rewrite::apply(&folder.rewrite, max_depth, path));
+
+    let mut sorted: Vec<&IndexedFile> = files.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut out = BTreeMap::new();
+    let mut taken = Taken::new(fs_rules.portable_names);
+    let mut laid_out = Vec::new();
+
+    // Natural paths first, so that laid out tracks never take them
+    for file in sorted {
+        let natural = natural_target(rules, &file.path);
+        let tags = layout
+            .filter(|_| is_tagged_file(&file.path))
+            .and_then(|_| idx.cached_tags(file))
+            .filter(|t| !t.is_empty());
+
+        match (layout, tags) {
+            (Some(template), Some(tags)) => {
+                let ext = Path::new(&natural).extension().map(|e| e.to_string_lossy().into_owned());
+                let path = render_layout(template, tags, &file.path, ext.as_deref().unwrap_or(""));
+                laid_out.push((file, finish(&path)));
+            }
+            _ => {
+                if layout.is_some() && is_tagged_file(&file.path) {
+                    tracing::debug!("No tags in {}, keeping its path", file.path);
+                }
+                let path = finish(&natural);
+                let changed = path != file.path;
+                if let Some(other) = taken.get(&path, changed) {
+                    anyhow::bail!(
+                        "Path collision in {}: {} and {} would both be stored as {}",
+                        folder.target, other, file.path, path
+                    );
+                }
+                taken.insert(&path, &file.path, changed);
+                out.insert(file.path.clone(), path);
+            }
+        }
+    }
+
+    for (file, path) in laid_out {
+        let mut candidate = path.clone();
+        let mut n = 1;
+        while taken.get(&candidate, true).is_some() {
+            n += 1;
+            candidate = numbered(&path, n);
+        }
+        taken.insert(&candidate, &file.path, true);
+        out.insert(file.path.clone(), candidate);
+    }
+
+    if let Some(max) = fs_rules.max_path {
+        let base = folder.target.trim_matches('/');
+        if let Some(path) = out.values().find(|p| base.len() + 1 + p.len() > max) {
+            anyhow::bail!("Path too long for {} (over {} bytes): {}/{}", task.device, max, base, path);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Move indexed files whose target path changed (tag edit, new template or rules) to their
+/// new path. Files that cannot be moved (output extension changed while re-uploaded,
+/// destination taken) are deleted and must be uploaded.
+/// Returns the freed target paths and the extra files to upload.
+pub fn move_targets(
+    target: &Path,
+    targets: &BTreeMap<String, String>,
+    uploads: &[IndexedFile],
+    idx: &mut Index,
+    journal: &mut Journal,
+    verbose: bool,
+) -> Result<(Vec<String>, Vec<IndexedFile>)> {
+    let uploading: HashSet<&str> = uploads.iter().map(|f| f.path.as_str()).collect();
+
+    let moves: Vec<(IndexedFile, String, String)> = idx
+        .files
+        .iter()
+        .filter_map(|f| {
+            let new = targets.get(&f.path)?;
+            let old = idx.target_path(&f.path);
+            (old != *new).then(|| (f.clone(), old, new.clone()))
+        })
+        .collect();
+
+    let mut freed = Vec::new();
+    let mut reupload = Vec::new();
+    let mut moved = 0;
+
+    for (file, old, new) in moves {
+        let same_ext = Path::new(&old).extension() == Path::new(&new).extension();
+        let busy = !old.eq_ignore_ascii_case(&new) && target.join(&new).exists();
+
+        if busy || (!same_ext && uploading.contains(file.path.as_str())) {
+            if verbose {
+                tracing::info!("DELETE {} (replaced by {})", old, new);
+            }
+            delete_one(target, &PathBuf::from(&old))?;
+            journal.record(&JournalEntry::Delete { path: file.path.clone() })?;
+            if !uploading.contains(file.path.as_str()) {
+                reupload.push(file);
+            }
+            freed.push(old);
+            continue;
+        }
+
+        if verbose {
+            tracing::info!("MOVE {} -> {}", old, new);
+        }
+
+        let from = target.join(&old);
+        let to = target.join(&new);
+        if from.exists() {
+            let created = create_parents(&to)?;
+            journal.record_dirs(target, &created)?;
+            idx.track_dirs(target, &created);
+            fs::rename(&from, &to)?;
+        }
+
+        let natural = idx.transcoded_as(&file.path).map_or_else(|| file.path.clone(), |t| t.output.clone());
+        let placed = Some(new.clone()).filter(|n| *n != natural);
+        journal.record(&JournalEntry::Place { path: file.path.clone(), target: placed.clone() })?;
+
+        idx.set_placed(&file.path, placed);
+        freed.push(old);
+        moved += 1;
+    }
+
+    if moved > 0 {
+        tracing::info!("Moved {} file(s) on {}.", moved, target.display());
+    }
+
+    Ok((freed, reupload))
+}
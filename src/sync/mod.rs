@@ -11,6 +11,14 @@ pub mod run;
 pub mod operations;
 pub mod validation;
 pub mod planner;
+pub mod import;
+pub mod transcode;
+pub mod hooks;
+pub mod playlists;
+pub mod tag_rules;
+pub mod layout;
+pub mod rewrite;
+pub mod formats;
 
 pub use detect::detect_all_devices;
 pub use detect_loop::start_detect_loop;
@@ -5,29 +5,103 @@
 
 use anyhow::Result;
 use std::path::{Path, PathBuf};
-use indicatif::ProgressBar;
+use indicatif::{ProgressBar, ProgressStyle};
 
-use crate::core::index::IndexedFile;
-use crate::utils::fs::{copy_one, delete_one};
+use crate::core::index::{parent_dirs, Index, IndexedFile};
+use crate::core::journal::{Journal, JournalEntry};
+use crate::sync::transcode::{rule_for, Transcoder};
+use crate::sync::layout::natural_target;
+use crate::utils::fs::{copy_file, delete_one, CopyOptions};
 
-/// Upload a batch of files with optional progress tracking
+/// How a sync reports its progress
+#[derive(Debug, Clone, Default)]
+pub enum Progress {
+    /// No progress reporting
+    #[default]
+    Quiet,
+    /// Progress bar on the terminal
+    Bar,
+    /// Hidden bar read by another thread (daemon status)
+    Track(ProgressBar),
+}
+
+impl Progress {
+    /// Bar for a batch of `len` operations, if progress is reported
+    pub fn start(&self, len: u64) -> Option<ProgressBar> {
+        match self {
+            Progress::Quiet => None,
+            Progress::Bar => {
+                let pb = ProgressBar::new(len);
+                pb.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{bar:40} {pos}/{len} | {wide_msg}")
+                        .unwrap(),
+                );
+                Some(pb)
+            }
+            Progress::Track(pb) => {
+                pb.set_length(len);
+                pb.set_position(0);
+                Some(pb.clone())
+            }
+        }
+    }
+}
+
+/// Upload a batch of files with optional progress tracking.
+/// Files matching a transcode rule are converted on the way, and each file
+/// is stored at its planned target path.
+/// Each completed upload is recorded in the journal. Stops early, between
+/// two files, once `copy.stop` is raised.
 pub fn upload_batch(
-    source: &Path,
+    transcoder: &Transcoder,
     target: &Path,
     files: &[IndexedFile],
     progress: Option<&ProgressBar>,
     verbose: bool,
+    copy: &mut CopyOptions,
+    journal: &mut Journal,
 ) -> Result<usize> {
     let mut count = 0;
 
     for file in files {
+        if copy.stop.is_stopped() {
+            break;
+        }
+
         let rel = PathBuf::from(&file.path);
 
         if verbose {
             tracing::info!("UPLOAD {}", rel.display());
         }
 
-        copy_one(source, target, &rel)?;
+        let natural = natural_target(transcoder.rules, &file.path);
+        let dest = transcoder.targets.get(&file.path).unwrap_or(&natural);
+        journal.record(&JournalEntry::Copying { target: dest.clone() })?;
+
+        let rule = rule_for(transcoder.rules, &file.path);
+        let from = match rule {
+            Some(rule) => transcoder.cached_output(file, rule)?,
+            None => transcoder.source.join(&rel),
+        };
+
+        // Directories are managed even when the copy fails or is stopped
+        let created = copy.created_dirs.len();
+        let copied = copy_file(&from, &target.join(dest), copy);
+        journal.record_dirs(target, &copy.created_dirs[created..])?;
+        copied?;
+
+        journal.record(&match rule {
+            Some(rule) => JournalEntry::Transcode {
+                file: file.clone(),
+                output: natural.clone(),
+                settings: rule.settings(),
+            },
+            None => JournalEntry::Upload { file: file.clone() },
+        })?;
+        if *dest != natural {
+            journal.record(&JournalEntry::Place { path: file.path.clone(), target: Some(dest.clone()) })?;
+        }
         count += 1;
 
         if let Some(pb) = progress {
@@ -39,23 +113,27 @@ pub fn upload_batch(
     Ok(count)
 }
 
-/// Delete a batch of files with optional progress tracking
+/// Delete a batch of source files from the target, with optional progress tracking.
+/// Transcoded files are looked up in the index. Each completed delete is recorded in the journal.
 pub fn delete_batch(
     target: &Path,
     paths: &[String],
+    idx: &Index,
     progress: Option<&ProgressBar>,
     verbose: bool,
+    journal: &mut Journal,
 ) -> Result<usize> {
     let mut count = 0;
 
     for rel_str in paths {
-        let rel = PathBuf::from(rel_str);
+        let rel = PathBuf::from(idx.target_path(rel_str));
 
         if verbose {
             tracing::info!("DELETE {}", rel.display());
         }
 
         delete_one(target, &rel)?;
+        journal.record(&JournalEntry::Delete { path: rel_str.clone() })?;
         count += 1;
 
         if let Some(pb) = progress {
@@ -66,3 +144,45 @@ pub fn delete_batch(
 
     Ok(count)
 }
+
+/// Remove managed directories left empty by deleting `deleted` files.
+/// Directories not in `managed` (e.g. created by the user) are never touched.
+/// Returns the number of directories removed.
+pub fn prune_empty_dirs(
+    target: &Path,
+    deleted: &[String],
+    managed: &mut Vec<String>,
+    verbose: bool,
+) -> Result<usize> {
+    let mut candidates: Vec<String> = deleted
+        .iter()
+        .flat_map(|p| parent_dirs(p))
+        .filter(|d| managed.contains(d))
+        .collect();
+
+    // Deepest first, so that parents emptied by their children go too
+    candidates.sort_by_cached_key(|d| (std::cmp::Reverse(Path::new(d).components().count()), d.clone()));
+    candidates.dedup();
+
+    let mut count = 0;
+
+    for rel in candidates {
+        let dir = target.join(&rel);
+
+        if dir.is_dir() && std::fs::read_dir(&dir)?.next().is_some() {
+            continue;
+        }
+
+        if dir.is_dir() {
+            if verbose {
+                tracing::info!("RMDIR {}", rel);
+            }
+            std::fs::remove_dir(&dir)?;
+            count += 1;
+        }
+
+        managed.retain(|d| *d != rel);
+    }
+
+    Ok(count)
+}
@@ -26,7 +26,7 @@ pub fn plan_sync(ctx: &ExecutionContext) -> Result<Vec<SyncTask>> {
 
 
     // Step 2: detect currently mounted devices
-    let mounted = detect_all_devices(&ctx);
+    let mounted = detect_all_devices(ctx);
 
     if mounted.is_empty() {
         return Ok(vec![]);
@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: MIT
+// src/sync/playlists.rs
+
+//! M3U playlists on the device: generated from the synced tracks, or
+//! rewritten from host playlists (Playlists folders).
+//! A playlist is only rewritten when its content changes.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{PlaylistConfig, PlaylistEncoding, PlaylistPaths};
+use crate::core::index::{Index, IndexedFile};
+use crate::core::scanner::scan_local_with;
+use crate::core::task::SyncTask;
+use crate::sync::operations::prune_empty_dirs;
+use crate::sync::validation::validate_sync_paths;
+use crate::utils::fs::{delete_one, remove_temp_files, write_atomic};
+use crate::utils::human::SyncStats;
+
+const TEST_WRITE: bool = true;
+
+/// Name of the playlist listing every track
+pub const ALL_TRACKS_NAME: &str = "All tracks";
+
+/// Playlist file extension for an encoding
+pub fn playlist_extension(encoding: PlaylistEncoding) -> &'static str {
+    match encoding {
+        PlaylistEncoding::Utf8 => "m3u8",
+        PlaylistEncoding::Latin1 => "m3u",
+    }
+}
+
+/// Whether a path has one of the given extensions (case-insensitive)
+pub fn has_extension(path: &str, extensions: &[String]) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| extensions.iter().any(|x| e.to_string_lossy().eq_ignore_ascii_case(x)))
+}
+
+/// Playlist file content: entries use '/' and are converted to `separator`.
+/// Lines starting with '#' (e.g. #EXTINF) are written as is.
+pub fn render(entries: &[String], separator: &str, encoding: PlaylistEncoding) -> Vec<u8> {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        if entry.starts_with('#') {
+            text.push_str(entry);
+        } else {
+            text.push_str(&entry.replace('/', separator));
+        }
+        text.push('\n');
+    }
+
+    match encoding {
+        PlaylistEncoding::Utf8 => text.into_bytes(),
+        PlaylistEncoding::Latin1 => text
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+            .collect(),
+    }
+}
+
+/// Entry for `track` in a playlist stored in `playlist_dir` (both relative to the target).
+/// `folder_target` is the target relative to the device root.
+pub fn entry_path(paths: PlaylistPaths, folder_target: &str, playlist_dir: &Path, track: &str) -> String {
+    match paths {
+        PlaylistPaths::Relative => Path::new(track)
+            .strip_prefix(playlist_dir)
+            .unwrap_or(Path::new(track))
+            .to_string_lossy()
+            .into_owned(),
+        PlaylistPaths::Absolute => {
+            let full = Path::new(folder_target.trim_matches('/')).join(track);
+            format!("/{}", full.to_string_lossy())
+        }
+    }
+}
+
+/// Playlists for a set of tracks (target paths), keyed by playlist path.
+pub fn plan_playlists(cfg: &PlaylistConfig, folder_target: &str, tracks: &[String]) -> BTreeMap<String, Vec<String>> {
+    let ext = playlist_extension(cfg.encoding);
+
+    let mut tracks: Vec<&String> = tracks.iter().filter(|t| has_extension(t, &cfg.extensions)).collect();
+    tracks.sort();
+
+    let mut groups: BTreeMap<PathBuf, Vec<&String>> = BTreeMap::new();
+
+    for track in &tracks {
+        let path = Path::new(track.as_str());
+        let parent = path.parent().unwrap_or(Path::new(""));
+        if parent.as_os_str().is_empty() {
+            continue;
+        }
+
+        if cfg.albums {
+            let name = parent.file_name().unwrap_or_default().to_string_lossy();
+            groups.entry(parent.join(format!("{}.{}", name, ext))).or_default().push(track);
+        }
+        if cfg.top_level {
+            let top = path.components().next().unwrap().as_os_str().to_string_lossy();
+            groups.entry(PathBuf::from(format!("{}.{}", top, ext))).or_default().push(track);
+        }
+    }
+
+    if cfg.all_tracks && !tracks.is_empty() {
+        groups.insert(PathBuf::from(format!("{}.{}", ALL_TRACKS_NAME, ext)), tracks.clone());
+    }
+
+    groups
+        .into_iter()
+        .map(|(playlist, tracks)| {
+            let dir = playlist.parent().unwrap_or(Path::new("")).to_path_buf();
+            let entries = tracks
+                .iter()
+                .map(|t| entry_path(cfg.paths, folder_target, &dir, t))
+                .collect();
+            (playlist.to_string_lossy().into_owned(), entries)
+        })
+        .collect()
+}
+
+/// Bring generated playlists in line with the synced `files`: write changed ones,
+/// delete those no longer produced (or all of them when `cfg` is None).
+/// Returns the number of playlists written and the deleted paths.
+pub fn write_playlists(
+    cfg: Option<&PlaylistConfig>,
+    folder_target: &str,
+    target: &Path,
+    files: &[IndexedFile],
+    idx: &mut Index,
+    verbose: bool,
+) -> Result<(usize, Vec<String>)> {
+    let Some(cfg) = cfg else {
+        return apply_playlists(&PlaylistConfig::default(), BTreeMap::new(), target, idx, verbose);
+    };
+
+    let tracks: Vec<String> = files.iter().map(|f| idx.target_path(&f.path)).collect();
+    let planned = plan_playlists(cfg, folder_target, &tracks);
+
+    apply_playlists(cfg, planned, target, idx, verbose)
+}
+
+/// Write planned playlists whose content changed and delete previously
+/// generated ones that are no longer planned.
+fn apply_playlists(
+    cfg: &PlaylistConfig,
+    planned: BTreeMap<String, Vec<String>>,
+    target: &Path,
+    idx: &mut Index,
+    verbose: bool,
+) -> Result<(usize, Vec<String>)> {
+    let mut written = 0;
+
+    // A run interrupted while writing a playlist left its temporary file behind
+    let known: Vec<String> = planned.keys().chain(&idx.playlists).cloned().collect();
+    remove_temp_files(target, &known)?;
+
+    for (rel, entries) in &planned {
+        let content = render(entries, &cfg.separator, cfg.encoding);
+        let path = target.join(rel);
+
+        // Unchanged playlists are not rewritten, sparing device writes
+        if fs::read(&path).is_ok_and(|old| old == content) {
+            continue;
+        }
+
+        if verbose {
+            tracing::info!("PLAYLIST {}", rel);
+        }
+        let created = write_atomic(&path, &content)?;
+        idx.track_dirs(target, &created);
+        written += 1;
+    }
+
+    let mut removed = Vec::new();
+    for rel in &idx.playlists {
+        if !planned.contains_key(rel) {
+            if verbose {
+                tracing::info!("DELETE {} (playlist)", rel);
+            }
+            delete_one(target, &PathBuf::from(rel))?;
+            removed.push(rel.clone());
+        }
+    }
+
+    idx.playlists = planned.into_keys().collect();
+
+    Ok((written, removed))
+}
+
+/// Path of `to` seen from the directory `from_dir`, both relative to the same root:
+/// ("Playlists", "Music/a.mp3") gives "../Music/a.mp3".
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to_parts: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to_parts).take_while(|(a, b)| a == b).count();
+
+    let mut out = PathBuf::new();
+    for _ in common..from.len() {
+        out.push("..");
+    }
+    for part in &to_parts[common..] {
+        out.push(part);
+    }
+    out
+}
+
+/// Resolve "." and ".." without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Lines of a host playlist: UTF-8, or Latin-1 for older `.m3u` files
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    let raw = fs::read(path).with_context(|| format!("Failed to read playlist: {}", path.display()))?;
+
+    let text = match String::from_utf8(raw) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|b| char::from(*b)).collect(),
+    };
+
+    Ok(text
+        .lines()
+        .map(|l| l.trim_start_matches('\u{feff}').trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+/// Rewrite host playlists of a Playlists folder to the device paths of its library folder.
+/// Tracks missing from the device are dropped; playlists are only written when they change.
+pub fn sync_host_playlists(task: &SyncTask, verbose: bool) -> Result<SyncStats> {
+    let library = task.library.as_deref().with_context(|| {
+        format!(
+            "Playlist folder {} needs `library` set to the target of a push folder of {}",
+            task.folder.target, task.device.name
+        )
+    })?;
+
+    let src = &task.source;
+    let dst = &task.target;
+    validate_sync_paths(src, dst, TEST_WRITE)?;
+
+    let cfg = task.folder.playlists.clone().unwrap_or_default();
+    let ext = playlist_extension(cfg.encoding);
+    let playlist_exts = ["m3u".to_string(), "m3u8".to_string()];
+
+    let lib_idx = library.load_index()?;
+    let on_device: HashSet<&str> = lib_idx.files.iter().map(|f| f.path.as_str()).collect();
+
+    let mut idx = task.load_index()?;
+    let host_files: Vec<IndexedFile> = scan_local_with(src, task.folder.symlinks)?
+        .into_iter()
+        .filter(|f| has_extension(&f.path, &playlist_exts))
+        .collect();
+
+    let mut planned = BTreeMap::new();
+    let mut dropped = 0;
+
+    for file in &host_files {
+        let host_path = src.join(&file.path);
+        let host_dir = host_path.parent().unwrap_or(src).to_path_buf();
+
+        let out_rel = Path::new(&file.path).with_extension(ext).to_string_lossy().into_owned();
+        let device_dir = Path::new(&task.folder.target)
+            .join(&out_rel)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+
+        let mut entries = Vec::new();
+        let mut extinf: Option<String> = None;
+
+        for line in read_lines(&host_path)? {
+            if line.starts_with("#EXTINF") {
+                extinf = Some(line);
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            // Entries are absolute, or relative to the playlist
+            let track = normalize(&host_dir.join(&line));
+            let rel = track.strip_prefix(&library.source).ok().map(|r| r.to_string_lossy().into_owned());
+
+            let Some(rel) = rel.filter(|r| on_device.contains(r.as_str())) else {
+                if verbose {
+                    tracing::info!("SKIP {} in {} (not on device)", line, file.path);
+                }
+                dropped += 1;
+                extinf = None;
+                continue;
+            };
+
+            let device_path = Path::new(&library.folder.target).join(lib_idx.target_path(&rel));
+            let entry = match cfg.paths {
+                PlaylistPaths::Relative => relative_path(&device_dir, &device_path),
+                PlaylistPaths::Absolute => Path::new("/").join(&device_path),
+            };
+
+            entries.extend(extinf.take());
+            entries.push(entry.to_string_lossy().into_owned());
+        }
+
+        planned.insert(out_rel, entries);
+    }
+
+    let (written, removed) = apply_playlists(&cfg, planned, dst, &mut idx, verbose)?;
+    prune_empty_dirs(dst, &removed, &mut idx.dirs, verbose)?;
+    idx.update(host_files)?;
+
+    println!(
+        "Playlists for {}: {} written, {} removed, {} track(s) not on the device.",
+        task.device,
+        written,
+        removed.len(),
+        dropped
+    );
+
+    Ok(SyncStats::new(written, removed.len(), 0, 0))
+}
@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: MIT
+// src/sync/rewrite.rs
+
+//! Path rewrite rules and directory flattening for players that only
+//! browse a few folder levels. Patterns are regexes matching the whole
+//! relative path; replacements expand their capture groups.
+
+use crate::config::RewriteRule;
+
+/// Separator joining flattened directory names
+pub const FLATTEN_SEPARATOR: &str = " - ";
+
+/// Rewrite a target path with the first rule whose regex matches all of it.
+/// Unknown capture groups expand to nothing; empty components are dropped.
+pub fn rewrite_path(rules: &[RewriteRule], path: &str) -> String {
+    for rule in rules {
+        if !rule.from.0.is_match(path) {
+            continue;
+        }
+
+        let out = rule.from.0.replace(path, rule.to.as_str());
+        return out.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>().join("/");
+    }
+    path.to_string()
+}
+
+/// Keep at most `max_depth` directories above a file by merging the deepest ones:
+/// with 1, `Artist/Album/CD1/01.mp3` becomes `Artist - Album - CD1/01.mp3`;
+/// with 0, files move to the root as `Artist - Album - CD1 - 01.mp3`.
+pub fn flatten(path: &str, max_depth: usize) -> String {
+    let parts: Vec<&str> = path.split('/').collect();
+    let (dirs, name) = parts.split_at(parts.len() - 1);
+
+    if dirs.len() <= max_depth {
+        return path.to_string();
+    }
+
+    if max_depth == 0 {
+        return [dirs, name].concat().join(FLATTEN_SEPARATOR);
+    }
+
+    let (kept, merged) = dirs.split_at(max_depth - 1);
+    let mut out: Vec<String> = kept.iter().map(|d| d.to_string()).collect();
+    out.push(merged.join(FLATTEN_SEPARATOR));
+    out.push(name[0].to_string());
+    out.join("/")
+}
+
+/// Final target path: rewrite rules first, then flattening
+pub fn apply(rules: &[RewriteRule], max_depth: Option<usize>, path: &str) -> String {
+    let path = rewrite_path(rules, path);
+    match max_depth {
+        Some(depth) => flatten(&path, depth),
+        None => path,
+    }
+}
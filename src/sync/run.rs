@@ -3,20 +3,28 @@
 
 //! Perform synchronization for a single folder pair.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::time::Instant;
 
+use crate::config::SyncMode;
 use crate::core::task::SyncTask;
 use crate::core::{
-    diff::compute_diff,
-    index::IndexedFile,
-    scanner::scan_local,
+    diff::{compute_diff, Diff},
+    index::{Index, IndexedFile},
+    journal::Journal,
+    scanner::scan_local_with,
 };
 use crate::utils::human::{human_size, notify, SyncStats};
-use crate::sync::operations::{upload_batch, delete_batch};
-use crate::sync::validation::validate_sync_paths;
-
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::utils::fs::{remove_temp_files, CopyOptions, StopSignal};
+use crate::sync::hooks::{run_task_hooks, HookEvent};
+use crate::sync::import::import_folder;
+use crate::sync::playlists::{sync_host_playlists, write_playlists};
+use crate::sync::operations::{upload_batch, delete_batch, prune_empty_dirs, Progress};
+use crate::sync::tag_rules::{filter_by_tags, refresh_tag_cache};
+use crate::sync::formats::{filter_formats, summarize, FormatFilter};
+use crate::sync::layout::{move_targets, natural_target, plan_targets};
+use crate::sync::transcode::{outdated, record_for, Transcoder};
+use crate::sync::validation::{validate_source, validate_sync_paths};
 
 const TEST_WRITE: bool = true;
 
@@ -26,7 +34,7 @@ fn total_size(files: &[IndexedFile]) -> u64 {
 }
 
 /// Calculate sync statistics from diff and previous index
-fn compute_sync_stats(diff: &crate::core::diff::Diff, previous: &[IndexedFile]) -> SyncStats {
+fn compute_sync_stats(diff: &Diff, previous: &[IndexedFile]) -> SyncStats {
     let upload_bytes = total_size(&diff.to_upload);
     let delete_bytes = diff.to_delete.iter()
         .filter_map(|rel| previous.iter().find(|f| f.path == *rel).map(|f| f.size))
@@ -40,9 +48,143 @@ fn compute_sync_stats(diff: &crate::core::diff::Diff, previous: &[IndexedFile])
     )
 }
 
-/// Perform a full sync for one folder pair
+/// Source files of a push task, once device formats and tag rules are applied
+struct Tracks {
+    files: Vec<IndexedFile>,
+    /// Files in a format the device cannot read
+    unsupported: Vec<IndexedFile>,
+    /// Synced files outside the profile formats, kept (see `FormatFilter`)
+    kept: Vec<String>,
+    /// Whether the tag cache of the index changed
+    tags_changed: bool,
+}
+
+/// Scan the source of a task, narrowed by the device formats and its tag rules.
+fn scan_tracks(task: &SyncTask, idx: &mut Index) -> Result<Tracks> {
+    let FormatFilter { files, unsupported, kept } =
+        filter_formats(task, scan_local_with(&task.source, task.folder.symlinks)?, &idx.files);
+
+    // Tags are only read when rules or a layout need them
+    let rules = &task.folder.tag_rules;
+    if rules.is_empty() && task.folder.layout.is_none() {
+        return Ok(Tracks { files, unsupported, kept, tags_changed: false });
+    }
+
+    let tags_changed = refresh_tag_cache(&task.source, &files, idx);
+    Ok(Tracks { files: filter_by_tags(rules, files, idx), unsupported, kept, tags_changed })
+}
+
+/// Compute pending changes for one folder pair, without touching
+/// the target, the index or the journal.
+pub fn pending_diff(task: &SyncTask) -> Result<(Diff, SyncStats)> {
+    let src = &task.source;
+    validate_source(src)?;
+
+    // Same starting point as a real sync: index plus pending journal entries
+    let mut idx = task.load_index()?;
+    let mut previous = idx.files.clone();
+    Journal::for_index(&task.index_path).fold(&mut previous)?;
+
+    let tracks = scan_tracks(task, &mut idx)?;
+    let local_files = tracks.files;
+    plan_targets(task, &local_files, &idx)?; // reports path collisions
+    let mut diff = compute_diff(&local_files, &previous);
+    diff.unsupported = tracks.unsupported;
+    diff.kept = tracks.kept;
+    diff.to_upload.extend(outdated(&task.folder.transcode, &local_files, &idx, &diff));
+
+    // Imports never delete: files removed from the device are simply forgotten
+    if matches!(task.mode, SyncMode::Import | SyncMode::Move) {
+        diff.to_delete.clear();
+    }
+
+    let stats = compute_sync_stats(&diff, &previous);
+
+    Ok((diff, stats))
+}
+
+/// Profile of the task device, for messages
+fn profile_name(task: &SyncTask) -> &str {
+    task.device.profile.as_deref().unwrap_or_default()
+}
+
+/// Print what a sync would do for one folder pair, without writing anything.
+pub fn preview_folder(task: &SyncTask) -> Result<SyncStats> {
+    let (diff, stats) = pending_diff(task)?;
+
+    let resumed = Journal::for_index(&task.index_path).operations()?;
+    if resumed > 0 {
+        println!("  (interrupted sync: {} operation(s) already done)", resumed);
+    }
+
+    for f in &diff.to_upload {
+        println!("  UPLOAD {} ({})", f.path, human_size(f.size));
+    }
+    for rel in &diff.to_delete {
+        println!("  DELETE {}", rel);
+    }
+    for f in &diff.unsupported {
+        println!("  SKIP {} (format not supported by {})", f.path, task.device);
+    }
+    for rel in &diff.kept {
+        println!("  KEEP {} (already synced, not in the {} profile formats)", rel, profile_name(task));
+    }
+
+    println!("  Planned: {}", stats.format_summary());
+
+    Ok(stats)
+}
+
+/// Perform a full sync for one folder pair, surrounded by its hooks.
+/// A failing `pre_sync` hook aborts the task.
 pub fn sync_folder(task: &SyncTask, verbose: bool, show_progress: bool) -> Result<()> {
-    
+    let progress = if show_progress { Progress::Bar } else { Progress::Quiet };
+    sync_folder_with(task, verbose, &progress, &StopSignal::new()).map(|_| ())
+}
+
+/// Same as [`sync_folder`], reporting progress as asked and returning what was done.
+/// Raising `stop` ends the sync after the current file; work done so far is kept
+/// and `None` is returned, without running the `post_sync` or `on_error` hooks.
+pub fn sync_folder_with(task: &SyncTask, verbose: bool, progress: &Progress, stop: &StopSignal) -> Result<Option<SyncStats>> {
+    let result = run_task_hooks(task, HookEvent::PreSync, None, None)
+        .context("pre_sync hook failed, sync aborted")
+        .and_then(|_| transfer_folder(task, verbose, progress, stop));
+
+    match result {
+        Ok(Some(stats)) => {
+            if let Err(e) = run_task_hooks(task, HookEvent::PostSync, Some(&stats), None) {
+                tracing::warn!("post_sync hook failed for {}: {:#}", task.device, e);
+            }
+            Ok(Some(stats))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            if let Err(hook_err) = run_task_hooks(task, HookEvent::Error, None, Some(&format!("{:#}", e))) {
+                tracing::warn!("on_error hook failed for {}: {:#}", task.device, hook_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Fold the operations of a stopped sync into its saved index, then report it.
+/// The remaining files are transferred by the next run.
+pub fn interrupted(task: &SyncTask, journal: &mut Journal) -> Result<Option<SyncStats>> {
+    let mut idx = task.load_index()?;
+    let done = journal.replay(&mut idx)?;
+    tracing::info!("Sync of {} stopped after {} operation(s), the next run completes it.", task.device, done);
+    Ok(None)
+}
+
+/// Transfer changes for one folder pair and return what was done, `None` when stopped
+fn transfer_folder(task: &SyncTask, verbose: bool, progress: &Progress, stop: &StopSignal) -> Result<Option<SyncStats>> {
+    if matches!(task.mode, SyncMode::Import | SyncMode::Move) {
+        return import_folder(task, verbose, progress, stop);
+    }
+    if matches!(task.mode, SyncMode::Playlists) {
+        return sync_host_playlists(task, verbose).map(Some);
+    }
+
     let start = Instant::now();
     let src = &task.source;
     let dst = &task.target;    
@@ -61,27 +203,82 @@ pub fn sync_folder(task: &SyncTask, verbose: bool, show_progress: bool) -> Resul
     // Load previous index (empty if missing)
     let mut idx = task.load_index()?; 
 
+    // Resume: drop the copy an interrupted run left half-written,
+    // then fold the operations it completed into the index
+    let mut journal = Journal::for_index(&task.index_path);
+    remove_temp_files(dst, &journal.in_progress()?)?;
+    let resumed = journal.replay(&mut idx)?;
+    if resumed > 0 {
+        println!("Resuming interrupted sync for {}: {} operation(s) already done.", task.device, resumed);
+    }
+
     // Scan source
-    let local_files = scan_local(src)?;
-    let diff = compute_diff(&local_files, &idx.files);    
+    let Tracks { files: local_files, unsupported, kept, tags_changed } = scan_tracks(task, &mut idx)?;
+    let mut diff = compute_diff(&local_files, &idx.files);
+
+    if !unsupported.is_empty() {
+        if verbose {
+            for f in &unsupported {
+                tracing::info!("SKIP {} (unsupported format)", f.path);
+            }
+        }
+        println!(
+            "Skipped {} file(s) {} cannot read ({}).",
+            unsupported.len(),
+            task.device,
+            summarize(&unsupported)
+        );
+    }
+    diff.unsupported = unsupported;
+    if !kept.is_empty() {
+        if verbose {
+            for rel in &kept {
+                tracing::info!("KEEP {} (not in the profile formats)", rel);
+            }
+        }
+        println!(
+            "Kept {} synced file(s) whose format the {} profile does not list; set `formats` on {} to remove them.",
+            kept.len(),
+            profile_name(task),
+            task.device
+        );
+    }
+    diff.to_upload.extend(outdated(&task.folder.transcode, &local_files, &idx, &diff));
 
 
-    // FIRST RUN: index did not exist, must write it even if diff empty
+    // FIRST RUN: index did not exist, must write it even if diff empty.
+    // It stays empty until files are actually transferred.
     if !idx.exists() {
-        idx.update(local_files.clone())?;
+        idx.save()?;
 
         tracing::info!("Initialized index for {}.", task.device);
 
         // If diff empty, nothing more to do
         if diff.to_upload.is_empty() && diff.to_delete.is_empty() {
-            return Ok(());
+            return Ok(Some(SyncStats::default()));
         }
     }
 
-    // Nothing to sync
+    // Files whose target path changed are moved on the device, not copied again
+    let rules = &task.folder.transcode;
+    let targets = plan_targets(task, &local_files, &idx)?;
+    let (mut removed, reupload) = move_targets(dst, &targets, &diff.to_upload, &mut idx, &mut journal, verbose)?;
+    diff.to_upload.extend(reupload);
+
+    // Nothing to sync, playlists may still follow a config change
     if diff.to_upload.is_empty() && diff.to_delete.is_empty() {
+        let (written, stale) = write_playlists(
+            task.folder.playlists.as_ref(), &task.folder.target, dst, &local_files, &mut idx, verbose,
+        )?;
+        removed.extend(stale);
+        if written > 0 || !removed.is_empty() || tags_changed {
+            prune_empty_dirs(dst, &removed, &mut idx.dirs, verbose)?;
+            idx.save()?;
+            journal.clear()?;
+        }
+
         println!("Nothing to synchronize for {}, everything is up to date.", task.device);
-        return Ok(());
+        return Ok(Some(SyncStats::default()));
     }
 
     // Compute sync statistics
@@ -97,26 +294,56 @@ pub fn sync_folder(task: &SyncTask, verbose: bool, show_progress: bool) -> Resul
 
     // Progress bar
     let total_ops = (diff.to_upload.len() + diff.to_delete.len()) as u64;
-    let pb = if show_progress {
-        let pb = ProgressBar::new(total_ops);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{bar:40} {pos}/{len} | {wide_msg}")
-                .unwrap(),
-        );
-        Some(pb)
-    } else {
-        None
-    };    
+    let pb = progress.start(total_ops);    
+
+    // Bandwidth limit is shared by the whole upload batch
+    let mut copy = CopyOptions { stop: stop.clone(), ..CopyOptions::new(task.max_bytes_per_sec, task.folder.preserve.clone()) };
+    if let Some(limit) = task.max_bytes_per_sec.filter(|l| *l > 0) {
+        tracing::info!("Upload bandwidth limited to {}/s.", human_size(limit));
+    }
+
+    let transcoder = Transcoder { source: src, rules, cache: &task.transcode_cache, targets: &targets };
+
+    // Target paths freed by moves and deletes, candidates for pruning
+    removed.extend(diff.to_delete.iter().map(|p| idx.target_path(p)));
 
     // Perform sync operations using batch functions
-    let done_upload = upload_batch(src, dst, &diff.to_upload, pb.as_ref(), verbose)?;
-    let done_delete = delete_batch(dst, &diff.to_delete, pb.as_ref(), verbose)?;
+    let done_upload = upload_batch(&transcoder, dst, &diff.to_upload, pb.as_ref(), verbose, &mut copy, &mut journal)?;
+    if stop.is_stopped() {
+        return interrupted(task, &mut journal);
+    }
+    idx.track_dirs(dst, &copy.created_dirs);
+    for f in &diff.to_upload {
+        let natural = natural_target(rules, &f.path);
+        let dest = targets.get(&f.path).cloned().unwrap_or_else(|| natural.clone());
+        idx.set_transcoded(&f.path, record_for(rules, &f.path));
+        idx.set_placed(&f.path, Some(dest).filter(|d| *d != natural));
+    }
+
+    let done_delete = delete_batch(dst, &diff.to_delete, &idx, pb.as_ref(), verbose, &mut journal)?;
+    for p in &diff.to_delete {
+        idx.set_transcoded(p, None);
+        idx.set_placed(p, None);
+    }
+
+    let (playlists, stale) = write_playlists(
+        task.folder.playlists.as_ref(), &task.folder.target, dst, &local_files, &mut idx, verbose,
+    )?;
+    removed.extend(stale);
+    if playlists > 0 {
+        tracing::info!("Updated {} playlist(s) on {}.", playlists, task.device);
+    }
+
+    let pruned = prune_empty_dirs(dst, &removed, &mut idx.dirs, verbose)?;
+    if pruned > 0 {
+        tracing::info!("Removed {} empty directories from {}.", pruned, dst.display());
+    }
 
     if let Some(pb) = pb { pb.finish(); }
 
-    // Save updated index
+    // Save updated index, the journal is no longer needed
     idx.update(local_files)?;
+    journal.clear()?;
 
     let elapsed = start.elapsed();
 
@@ -137,5 +364,5 @@ pub fn sync_folder(task: &SyncTask, verbose: bool, show_progress: bool) -> Resul
         )
     );
 
-    Ok(())
+    Ok(Some(SyncStats::new(done_upload, done_delete, stats.upload_bytes, stats.delete_bytes)))
 }
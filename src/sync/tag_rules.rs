@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: MIT
+// src/sync/tag_rules.rs
+
+//! Selection of source tracks by embedded tags (genre, artist, rating…).
+//! Tags are cached in the index and only re-read when a file changes.
+
+use std::path::Path;
+
+use crate::config::{TagCondition, TagRule, TagRules};
+use crate::core::index::{CachedTags, Index, IndexedFile};
+use crate::core::tags::{is_tagged_file, read_tags, Tags};
+
+type Comparison = fn(f64, f64) -> bool;
+
+/// Leading number of a tag value: "3/12" gives 3, "1999-05-01" gives 1999
+fn leading_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let end = value.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// Case-insensitive match with `*` wildcards
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let value = value.to_lowercase();
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+
+    let mut rest = value.as_str();
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            match rest.strip_prefix(part) {
+                Some(r) => rest = r,
+                None => return false,
+            }
+        } else if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        } else {
+            match rest.find(part) {
+                Some(pos) => rest = &rest[pos + part.len()..],
+                None => return false,
+            }
+        }
+    }
+    true
+}
+
+/// Whether one tag value satisfies one pattern
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.trim();
+
+    let comparisons: [(&str, Comparison); 4] = [
+        (">=", |v, l| v >= l),
+        ("<=", |v, l| v <= l),
+        (">", |v, l| v > l),
+        ("<", |v, l| v < l),
+    ];
+
+    for (op, compare) in comparisons {
+        if let Some(limit) = pattern.strip_prefix(op) {
+            return match (leading_number(value), limit.trim().parse::<f64>()) {
+                (Some(v), Ok(limit)) => compare(v, limit),
+                _ => false,
+            };
+        }
+    }
+
+    if let Some((low, high)) = pattern.split_once("..") {
+        return match (leading_number(value), low.trim().parse::<f64>(), high.trim().parse::<f64>()) {
+            (Some(v), Ok(low), Ok(high)) => low <= v && v <= high,
+            _ => false,
+        };
+    }
+
+    wildcard_match(pattern, value)
+}
+
+/// Whether any value of a tag satisfies any pattern of the condition
+pub fn condition_matches(condition: &TagCondition, values: &[String]) -> bool {
+    values.iter().any(|v| condition.0.iter().any(|p| pattern_matches(p, v)))
+}
+
+/// A rule matches when every one of its tags matches
+pub fn rule_matches(rule: &TagRule, tags: &Tags) -> bool {
+    rule.iter()
+        .all(|(key, condition)| condition_matches(condition, tags.values(&key.to_lowercase())))
+}
+
+/// Whether a track with these tags is selected by the rules
+pub fn is_selected(rules: &TagRules, tags: &Tags) -> bool {
+    let included = rules.include.is_empty() || rules.include.iter().any(|r| rule_matches(r, tags));
+    let excluded = rules.exclude.iter().any(|r| rule_matches(r, tags));
+    included && !excluded
+}
+
+/// Bring the tag cache in line with the scanned files, reading tags only
+/// for new or changed tracks. Returns whether the cache changed.
+pub fn refresh_tag_cache(source: &Path, files: &[IndexedFile], idx: &mut Index) -> bool {
+    let mut cache = Vec::new();
+    let mut read = 0;
+
+    for file in files.iter().filter(|f| is_tagged_file(&f.path)) {
+        let tags = match idx.cached_tags(file) {
+            Some(tags) => tags.clone(),
+            None => {
+                read += 1;
+                read_tags(&source.join(&file.path)).unwrap_or_else(|e| {
+                    tracing::warn!("Cannot read tags of {}: {}", file.path, e);
+                    Tags::default()
+                })
+            }
+        };
+        cache.push(CachedTags { path: file.path.clone(), size: file.size, mtime: file.mtime, tags });
+    }
+
+    cache.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if read > 0 {
+        tracing::debug!("Read tags of {} file(s)", read);
+    }
+
+    let changed = cache != idx.tag_cache;
+    idx.tag_cache = cache;
+    changed
+}
+
+/// Keep files selected by the rules. Files without tag support (covers,
+/// booklets…) are not affected. Expects a refreshed tag cache.
+pub fn filter_by_tags(rules: &TagRules, files: Vec<IndexedFile>, idx: &Index) -> Vec<IndexedFile> {
+    if rules.is_empty() {
+        return files;
+    }
+
+    files
+        .into_iter()
+        .filter(|f| {
+            !is_tagged_file(&f.path)
+                || is_selected(rules, idx.cached_tags(f).unwrap_or(&Tags::default()))
+        })
+        .collect()
+}
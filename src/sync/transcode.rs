@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: MIT
+// src/sync/transcode.rs
+
+//! Transcoding of uploads through an external command (e.g. FLAC to Opus).
+//! Outputs are kept in a host cache shared by every device, keyed by source
+//! file and settings, so a second device reuses them. `clean_cache` trims it.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, FileTimes};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::TranscodeRule;
+use crate::core::diff::Diff;
+use crate::core::index::{Index, IndexedFile, TranscodedFile};
+use crate::utils::heartbeat::pulse;
+
+/// Source side of an upload batch: files are copied as is,
+/// or transcoded when a rule matches their extension.
+#[derive(Debug)]
+pub struct Transcoder<'a> {
+    pub source: &'a Path,
+    pub rules: &'a [TranscodeRule],
+    pub cache: &'a Path,
+    /// Target path of each source file (see `layout::plan_targets`)
+    pub targets: &'a BTreeMap<String, String>,
+}
+
+/// First rule matching a source path
+pub fn rule_for<'a>(rules: &'a [TranscodeRule], path: &str) -> Option<&'a TranscodeRule> {
+    rules.iter().find(|r| r.matches(path))
+}
+
+/// Transcoding record of a source file under the current rules
+pub fn record_for(rules: &[TranscodeRule], path: &str) -> Option<TranscodedFile> {
+    rule_for(rules, path).map(|rule| TranscodedFile {
+        source: path.to_string(),
+        output: rule.output_path(path),
+        settings: rule.settings(),
+    })
+}
+
+/// Unchanged source files whose transcoding settings differ from the index,
+/// e.g. after a bitrate change. They must be uploaded again.
+pub fn outdated(rules: &[TranscodeRule], local: &[IndexedFile], idx: &Index, diff: &Diff) -> Vec<IndexedFile> {
+    let indexed: HashSet<&str> = idx.files.iter().map(|f| f.path.as_str()).collect();
+    let pending: HashSet<&str> = diff.to_upload.iter().map(|f| f.path.as_str()).collect();
+
+    local
+        .iter()
+        .filter(|f| indexed.contains(f.path.as_str()) && !pending.contains(f.path.as_str()))
+        .filter(|f| record_for(rules, &f.path).as_ref() != idx.transcoded_as(&f.path))
+        .cloned()
+        .collect()
+}
+
+impl Transcoder<'_> {
+    /// Transcoded output of a source file, produced on a cache miss.
+    pub fn cached_output(&self, file: &IndexedFile, rule: &TranscodeRule) -> Result<PathBuf> {
+        let input = self.source.join(&file.path);
+
+        let key = blake3::hash(
+            format!("{}\0{}\0{}\0{}", input.display(), file.size, file.mtime, rule.settings()).as_bytes(),
+        )
+        .to_hex();
+
+        let output = self.cache.join(format!("{}.{}", key, rule.to));
+        if output.exists() {
+            tracing::debug!("Transcode cache hit for {}", file.path);
+            return Ok(output);
+        }
+
+        fs::create_dir_all(self.cache)
+            .with_context(|| format!("Failed to create transcode cache: {}", self.cache.display()))?;
+
+        // Each transcode writes into its own directory, so concurrent syncs of
+        // the same file never share a partial output. The command may pick the
+        // format from the extension, keep it last.
+        let work = tempfile::Builder::new()
+            .prefix(PARTIAL_PREFIX)
+            .tempdir_in(self.cache)
+            .with_context(|| format!("Failed to create a directory in {}", self.cache.display()))?;
+        let partial = work.path().join(format!("{}.{}", key, rule.to));
+
+        run_command(rule, &input, &partial)?;
+
+        // Outputs carry the source mtime, which copies then preserve
+        let mtime = UNIX_EPOCH + Duration::from_secs(file.mtime.max(0) as u64);
+        fs::File::options()
+            .write(true)
+            .open(&partial)?
+            .set_times(FileTimes::new().set_modified(mtime))?;
+
+        fs::rename(&partial, &output)?;
+        Ok(output)
+    }
+}
+
+/// Prefix of the directories holding transcodes in progress
+const PARTIAL_PREFIX: &str = ".part-";
+
+/// Leftovers of interrupted transcodes older than this are removed by `clean_cache`
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often a running transcode command is checked
+const TRANSCODE_POLL: Duration = Duration::from_millis(50);
+
+/// Remove cached outputs added more than `older_than` ago (all of them when
+/// None) and leftovers of interrupted transcodes. Removed outputs are simply
+/// produced again when needed. Returns the number of entries and bytes freed.
+pub fn clean_cache(cache: &Path, older_than: Option<Duration>) -> Result<(usize, u64)> {
+    let Ok(entries) = fs::read_dir(cache) else {
+        return Ok((0, 0));
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let (mut count, mut bytes) = (0, 0);
+
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        // Outputs carry the source mtime; ctime is when they entered the cache
+        let age = Duration::from_secs((now - meta.ctime()).max(0) as u64);
+
+        if entry.file_name().to_string_lossy().starts_with(PARTIAL_PREFIX) {
+            if meta.is_dir() && age >= PARTIAL_MAX_AGE {
+                fs::remove_dir_all(entry.path())?;
+                count += 1;
+            }
+            continue;
+        }
+
+        if meta.is_file() && older_than.is_none_or(|limit| age >= limit) {
+            fs::remove_file(entry.path())?;
+            count += 1;
+            bytes += meta.len();
+        }
+    }
+
+    Ok((count, bytes))
+}
+
+/// Run the rule command for one file. Placeholders are replaced after
+/// splitting on whitespace, so paths containing spaces stay one argument.
+fn run_command(rule: &TranscodeRule, input: &Path, output: &Path) -> Result<()> {
+    // Errors go to a file next to the output, read once the command is done
+    let errors = output.with_file_name("stderr");
+    let partial = output;
+    let input = input.to_string_lossy();
+    let output = output.to_string_lossy();
+
+    let mut args = rule
+        .command
+        .split_whitespace()
+        .map(|arg| arg.replace("{input}", &input).replace("{output}", &output));
+
+    let program = args.next().context("Empty transcode command")?;
+
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(fs::File::create(&errors)?)
+        .spawn()
+        .with_context(|| format!("Failed to run transcode command: {}", program))?;
+
+    // A long transcode is alive as long as its output grows
+    let mut written = 0;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let size = fs::metadata(partial).map_or(0, |m| m.len());
+        if size > written {
+            written = size;
+            pulse();
+        }
+        thread::sleep(TRANSCODE_POLL);
+    };
+
+    if !status.success() {
+        anyhow::bail!(
+            "Transcoding {} failed ({}): {}",
+            input,
+            status,
+            fs::read_to_string(&errors).unwrap_or_default().trim()
+        );
+    }
+
+    Ok(())
+}
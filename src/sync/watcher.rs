@@ -9,25 +9,32 @@ use std::path::PathBuf;
 use std::sync::mpsc::{channel, Sender};
 use std::time::{Duration, Instant};
 
+use crate::utils::heartbeat::Heartbeat;
+
 /// Public controller used by DaemonState to add/remove watched sources.
 #[derive(Clone, Debug)]
 pub struct WatcherControl {
     tx_cmd: Sender<WatcherCommand>,
+    heartbeat: Heartbeat,
 }
 
 enum WatcherCommand {
     Add(PathBuf),
     Remove(PathBuf),
     ClearAll,
+    SetDebounce(u64),
+    Stop,
 }
 
 /// Start the watcher thread. Returns a handle allowing dynamic updates.
 pub fn start_watcher(
-    debounce_ms: u64,
+    mut debounce_ms: u64,
     on_batch: impl Fn(Vec<Event>) + Send + 'static,
 ) -> Result<WatcherControl> {
     let (tx_cmd, rx_cmd) = channel::<WatcherCommand>();
     let (tx_ev, rx_ev) = channel::<Result<Event, notify::Error>>();
+    let heartbeat = Heartbeat::new();
+    let beat = heartbeat.clone();
 
     std::thread::spawn(move || {
         let mut watcher =
@@ -38,16 +45,18 @@ pub fn start_watcher(
         let mut last: Option<Instant> = None;
 
         loop {
+            beat.beat();
+
             // Apply control commands
             if let Ok(cmd) = rx_cmd.try_recv() {
                 match cmd {
                     WatcherCommand::Add(path) => {
                         let display_str = path.display().to_string();
-                        if !watched.contains(&path) {
-                            if watcher.watch(&path, RecursiveMode::Recursive).is_ok() {
-                                watched.push(path.clone());
-                                tracing::info!("Now watching {}", display_str);
-                            }
+                        if !watched.contains(&path)
+                            && watcher.watch(&path, RecursiveMode::Recursive).is_ok()
+                        {
+                            watched.push(path.clone());
+                            tracing::info!("Now watching {}", display_str);
                         }
                     }
                     WatcherCommand::Remove(path) => {
@@ -62,6 +71,13 @@ pub fn start_watcher(
                         }
                         tracing::info!("Watcher: cleared all paths.");
                     }
+                    WatcherCommand::SetDebounce(ms) => {
+                        debounce_ms = ms;
+                    }
+                    WatcherCommand::Stop => {
+                        tracing::info!("Watcher stopped.");
+                        break;
+                    }
                 }
             }
 
@@ -75,7 +91,7 @@ pub fn start_watcher(
             if let Some(t) = last {
                 if t.elapsed() >= Duration::from_millis(debounce_ms) {
                     if !pending.is_empty() {
-                        let batch = pending.drain(..).collect();
+                        let batch = std::mem::take(&mut pending);
                         on_batch(batch);
                     }
                     last = None;
@@ -86,7 +102,7 @@ pub fn start_watcher(
         }
     });
 
-    Ok(WatcherControl { tx_cmd })
+    Ok(WatcherControl { tx_cmd, heartbeat })
 }
 
 impl WatcherControl {
@@ -101,4 +117,18 @@ impl WatcherControl {
     pub fn clear_all(&self) {
         let _ = self.tx_cmd.send(WatcherCommand::ClearAll);
     }
+
+    /// Liveness of the watcher thread, stale while a batch is being handled
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// End the watcher thread; pending events are dropped
+    pub fn stop(&self) {
+        let _ = self.tx_cmd.send(WatcherCommand::Stop);
+    }
+
+    pub fn set_debounce(&self, debounce_ms: u64) {
+        let _ = self.tx_cmd.send(WatcherCommand::SetDebounce(debounce_ms));
+    }
 }
@@ -5,7 +5,20 @@
 
 use anyhow::Result;
 use std::path::{ Path, PathBuf};
-use std::fs;
+use std::fs::{self, FileTimes};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::PreserveConfig;
+use crate::utils::heartbeat::pulse;
+use crate::utils::throttle::Throttle;
+
+/// Buffer size used for copies and hashing
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Suffix of in-progress copies, renamed into place once complete
+pub const TEMP_SUFFIX: &str = ".plainsync-tmp";
 
 
 /// Ensure path is writable
@@ -16,16 +29,274 @@ pub fn ensure_writable(path: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Copy one file from source to target
-pub fn copy_one(source: &Path, target: &Path, file: &PathBuf) -> Result<()> {
-    let src = source.join(file);
-    let dst = target.join(file);
+/// Temporary sibling used while `dst` is being written, e.g. `.song.flac.plainsync-tmp`
+pub fn temp_path(dst: &Path) -> PathBuf {
+    let name = dst.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    dst.with_file_name(format!(".{}{}", name, TEMP_SUFFIX))
+}
+
+/// Request to stop a sync after the file being copied, shared between threads
+#[derive(Debug, Clone, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Settings shared by every copy of a batch
+#[derive(Debug, Default)]
+pub struct CopyOptions {
+    pub throttle: Throttle,
+    pub preserve: PreserveConfig,
+    /// Checked before each file: once stopped, no new copy starts
+    pub stop: StopSignal,
+    /// Directories created for the copies so far
+    pub created_dirs: Vec<PathBuf>,
+}
+
+impl CopyOptions {
+    pub fn new(max_bytes_per_sec: Option<u64>, preserve: PreserveConfig) -> Self {
+        Self { throttle: Throttle::new(max_bytes_per_sec), preserve, ..Default::default() }
+    }
+}
+
+/// Create the missing parent directories of `path`.
+/// Returns those created by this call, outermost first.
+pub fn create_parents(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut missing = Vec::new();
+    let mut current = path.parent();
+    while let Some(dir) = current.filter(|d| !d.as_os_str().is_empty() && !d.exists()) {
+        missing.push(dir.to_path_buf());
+        current = dir.parent();
+    }
+
+    let mut created = Vec::new();
+    for dir in missing.into_iter().rev() {
+        match fs::create_dir(&dir) {
+            Ok(()) => created.push(dir),
+            // Created by someone else in the meantime
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
 
+    Ok(created)
+}
+
+/// Copy one file from source to target.
+/// The file is written to a temporary name, flushed, then renamed into place,
+/// so an interrupted copy never leaves a truncated file under the final name.
+pub fn copy_one(source: &Path, target: &Path, file: &PathBuf, opts: &mut CopyOptions) -> Result<()> {
+    copy_file(&source.join(file), &target.join(file), opts)
+}
+
+/// Copy `src` to `dst` atomically (see `copy_one`), destination path chosen by the caller.
+pub fn copy_file(src: &Path, dst: &Path, opts: &mut CopyOptions) -> Result<()> {
+    let tmp = temp_path(dst);
+
+    opts.created_dirs.extend(create_parents(dst)?);
+
+    let result = write_temp(src, &tmp, opts).and_then(|_| {
+        fs::rename(&tmp, dst)?;
+        Ok(())
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+
+    // Persist the rename itself (best effort, not supported everywhere)
     if let Some(parent) = dst.parent() {
-        fs::create_dir_all(parent)?;
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// Write generated content to `dst` atomically (temporary name, flush, rename).
+/// Returns the directories created for it.
+pub fn write_atomic(dst: &Path, content: &[u8]) -> Result<Vec<PathBuf>> {
+    let tmp = temp_path(dst);
+    let created = create_parents(dst)?;
+
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, dst));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
     }
-    // std::thread::sleep(std::time::Duration::from_millis(1000)); // Simulate delay
-    fs::copy(src, dst)?;
+
+    Ok(created)
+}
+
+/// Write `src` into `tmp`, apply preserved metadata and flush it to the device.
+/// The temp file is created with the default mode, so a read-only source can
+/// still be written; its own mode is only applied last, when preserved.
+fn write_temp(src: &Path, tmp: &Path, opts: &mut CopyOptions) -> Result<()> {
+    let mut reader = fs::File::open(src)?;
+    let _ = fs::remove_file(tmp);
+    let mut file = fs::File::create(tmp)?;
+
+    copy_chunks(&mut reader, &mut file, &mut opts.throttle)?;
+
+    preserve_metadata(src, &file, &opts.preserve)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Carry source metadata over to `dst` according to the policy.
+/// Attributes the target filesystem cannot store only produce a warning.
+/// Permissions come last: a read-only mode would block the other updates.
+pub fn preserve_metadata(src: &Path, dst: &fs::File, policy: &PreserveConfig) -> Result<()> {
+    let meta = fs::metadata(src)?;
+
+    let mut times = FileTimes::new();
+    if policy.mtime {
+        times = times.set_modified(meta.modified()?);
+    }
+    if policy.atime {
+        times = times.set_accessed(meta.accessed()?);
+    }
+    if policy.mtime || policy.atime {
+        if let Err(e) = dst.set_times(times) {
+            tracing::warn!("Cannot preserve timestamps of {}: {}", src.display(), e);
+        }
+    }
+
+    if policy.xattrs {
+        if let Err(e) = copy_xattrs(src, dst) {
+            tracing::warn!("Cannot preserve extended attributes of {}: {}", src.display(), e);
+        }
+    }
+
+    if policy.permissions {
+        if let Err(e) = dst.set_permissions(meta.permissions()) {
+            tracing::warn!("Cannot preserve permissions of {}: {}", src.display(), e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy every extended attribute of `src` onto `dst`.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(src: &Path, dst: &fs::File) -> std::io::Result<()> {
+    use nix::libc;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let path = CString::new(src.as_os_str().as_bytes())?;
+
+    // SAFETY: buffers are sized from the lengths reported by the kernel
+    // and the pointers stay valid for the duration of each call.
+    unsafe {
+        let len = libc::llistxattr(path.as_ptr(), std::ptr::null_mut(), 0);
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut names = vec![0u8; len as usize];
+        let len = libc::llistxattr(path.as_ptr(), names.as_mut_ptr().cast(), names.len());
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        names.truncate(len as usize);
+
+        for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+            let name = CString::new(name)?;
+
+            let size = libc::lgetxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let mut value = vec![0u8; size as usize];
+            let size = libc::lgetxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len());
+            if size < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            let res = libc::fsetxattr(dst.as_raw_fd(), name.as_ptr(), value.as_ptr().cast(), size as usize, 0);
+            if res != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_src: &Path, _dst: &fs::File) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Hash file content (blake3, hex encoded)
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        pulse();
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Remove the leftovers of interrupted copies to `targets` (relative to `root`).
+/// Returns the number of files removed.
+pub fn remove_temp_files(root: &Path, targets: &[String]) -> Result<usize> {
+    let mut count = 0;
+
+    for target in targets {
+        let tmp = temp_path(&root.join(target));
+        if tmp.is_file() {
+            tracing::info!("Removing stale temporary file {}", tmp.display());
+            fs::remove_file(&tmp)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Copy a file chunk by chunk, letting the throttle pace the writes.
+/// Each chunk pulses the heartbeat of the thread, however long the file.
+fn copy_chunks(reader: &mut fs::File, writer: &mut fs::File, throttle: &mut Throttle) -> Result<()> {
+    let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buf[..n])?;
+        throttle.consume(n as u64);
+        pulse();
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MIT
+// src/utils/heartbeat.rs
+
+//! Liveness of a background loop, beaten on every pass and read by the watchdog.
+//! Long operations inside a pass (copying a large file, waiting for a transcode,
+//! scanning a big tree) [`pulse`] the heartbeat their thread runs under.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Heartbeat of the work running on this thread, see [`Heartbeat::around`]
+    static CURRENT: RefCell<Option<Heartbeat>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Record that the loop is alive
+    pub fn beat(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the last beat
+    pub fn age(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+
+    /// Run `f`, beating this heartbeat on each [`pulse`] it makes on this thread
+    pub fn around<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = CURRENT.with(|c| c.replace(Some(self.clone())));
+        let out = f();
+        CURRENT.with(|c| *c.borrow_mut() = previous);
+        out
+    }
+}
+
+/// Record that the work of this thread is moving on, e.g. after a chunk copied.
+/// Does nothing outside [`Heartbeat::around`].
+pub fn pulse() {
+    CURRENT.with(|c| {
+        if let Some(hb) = &*c.borrow() {
+            hb.beat();
+        }
+    });
+}
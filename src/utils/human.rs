@@ -69,6 +69,14 @@ impl SyncStats {
         Self { upload_count, delete_count, upload_bytes, delete_bytes }
     }
 
+    /// Add the counters of another run to this one.
+    pub fn merge(&mut self, other: &SyncStats) {
+        self.upload_count += other.upload_count;
+        self.delete_count += other.delete_count;
+        self.upload_bytes += other.upload_bytes;
+        self.delete_bytes += other.delete_bytes;
+    }
+
     pub fn has_changes(&self) -> bool {
         self.upload_count > 0 || self.delete_count > 0
     }
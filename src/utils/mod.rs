@@ -7,4 +7,6 @@ pub mod slug;
 pub mod human;
 pub mod fs;
 pub mod editor;
-pub mod paths;
\ No newline at end of file
+pub mod paths;
+pub mod throttle;
+pub mod heartbeat;
\ No newline at end of file
@@ -3,7 +3,9 @@
 
 //! Path resolution utilities.
 
-use std::path::PathBuf;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 
 use crate::context::{EnvContext, PathExpander};
@@ -22,6 +24,13 @@ pub fn config_dir() -> Result<PathBuf> {
         .context("Failed to locate user config directory")
 }
 
+/// Get cache directory with fallback
+pub fn cache_dir() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .ok_or_else(|| anyhow::anyhow!("No cache directory found"))
+        .context("Failed to locate user cache directory")
+}
+
 /// Get home directory with fallback
 pub fn home_dir() -> Result<PathBuf> {
     dirs::home_dir()
@@ -34,11 +43,47 @@ pub fn app_data_dir() -> Result<PathBuf> {
     Ok(data_dir()?.join("plainsync"))
 }
 
+/// Get plainsync cache directory
+pub fn app_cache_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("plainsync"))
+}
+
 /// Get plainsync config directory
 pub fn app_config_dir() -> Result<PathBuf> {
     Ok(config_dir()?.join("plainsync"))
 }
 
+/// Get the daemon control socket, in `$XDG_RUNTIME_DIR` when it is set,
+/// otherwise in a per-user directory of the temporary directory
+pub fn control_socket() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("plainsync.sock"),
+        None => std::env::temp_dir()
+            .join(format!("plainsync-{}", users::get_current_uid()))
+            .join("plainsync.sock"),
+    }
+}
+
+/// Create a directory only the current user can enter, or check an existing
+/// one is: in a shared directory, another user could have created it first.
+pub fn private_dir(path: &Path) -> Result<()> {
+    match fs::DirBuilder::new().recursive(true).mode(0o700).create(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create directory: {}", path.display())),
+    }
+
+    let meta = fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to read directory: {}", path.display()))?;
+    if !meta.is_dir() || meta.uid() != users::get_current_uid() || meta.mode() & 0o077 != 0 {
+        anyhow::bail!(
+            "{} must be a directory owned by the current user and closed to others (mode 700)",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
 /// Ensure directory exists
 pub fn ensure_dir(path: &PathBuf) -> Result<()> {
     if !path.exists() {
@@ -20,8 +20,7 @@ pub fn unique_slug(label: &str, context: &str) -> String {
     // Keep the label readable and slug-like
     let slug = label
         .to_lowercase()
-        .replace(' ', "-")
-        .replace('/', "-");
+        .replace([' ', '/'], "-");
 
     format!("{}-{}", slug, short)
 }
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT
+// src/utils/throttle.rs
+
+//! Bandwidth throttling and process priority helpers for background syncs.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Best-effort I/O scheduling class (see ioprio_set(2)).
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_BE: i32 = 2;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+/// Lowest priority level within the best-effort class.
+#[cfg(target_os = "linux")]
+const IOPRIO_LOWEST_LEVEL: i32 = 7;
+
+/// Nice value applied to low-priority syncs.
+const LOW_PRIORITY_NICE: i32 = 10;
+
+/// Limits the average throughput of a sequence of writes.
+#[derive(Debug)]
+pub struct Throttle {
+    limit: Option<u64>,
+    /// Start of the window, set by the first write
+    started: Option<Instant>,
+    consumed: u64,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl Throttle {
+    /// Create a throttle allowing `limit` bytes per second (None = unlimited).
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit: limit.filter(|l| *l > 0),
+            started: None,
+            consumed: 0,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None)
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Account for `bytes` written, sleeping until the average rate is back under the limit.
+    pub fn consume(&mut self, bytes: u64) {
+        let Some(limit) = self.limit else { return };
+
+        // Time spent before the first write (hashing, transcoding) is not credit
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.consumed += bytes;
+
+        let expected = Duration::from_secs_f64(self.consumed as f64 / limit as f64);
+        let elapsed = started.elapsed();
+
+        if expected > elapsed {
+            thread::sleep(expected - elapsed);
+        }
+    }
+}
+
+/// Lower CPU and I/O priority of the calling thread.
+/// Threads spawned afterwards inherit the new priority.
+pub fn lower_priority() {
+    // SAFETY: plain syscalls on the calling thread, no memory is shared.
+    let nice = unsafe { nix::libc::setpriority(nix::libc::PRIO_PROCESS, 0, LOW_PRIORITY_NICE) };
+    if nice != 0 {
+        tracing::warn!("Failed to lower CPU priority: {}", std::io::Error::last_os_error());
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | IOPRIO_LOWEST_LEVEL;
+        // SAFETY: see above.
+        let res = unsafe {
+            nix::libc::syscall(nix::libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio)
+        };
+        if res != 0 {
+            tracing::warn!("Failed to lower I/O priority: {}", std::io::Error::last_os_error());
+        }
+    }
+
+    tracing::info!("Running syncs with lowered CPU and I/O priority.");
+}
@@ -238,6 +238,44 @@ mod index_tests {
         assert_eq!(loaded[1].size, 200);
     }
 
+    #[test]
+    fn test_load_version_1_index() {
+        #[derive(serde::Serialize)]
+        struct IndexV1 {
+            version: u32,
+            generated_at: i64,
+            files: Vec<IndexedFile>,
+            total_files: usize,
+            total_size: u64,
+            avg_size: u64,
+            biggest: Option<IndexedFile>,
+            newest: Option<IndexedFile>,
+            oldest: Option<IndexedFile>,
+        }
+
+        let temp = TempDir::new().unwrap();
+        let index_path = temp.path().join("v1.bin");
+        let old = IndexV1 {
+            version: 1,
+            generated_at: 1234,
+            files: vec![IndexedFile { path: "a.txt".to_string(), size: 10, mtime: 1000 }],
+            total_files: 1,
+            total_size: 10,
+            avg_size: 10,
+            biggest: None,
+            newest: None,
+            oldest: None,
+        };
+        fs::write(&index_path, bincode::serialize(&old).unwrap()).unwrap();
+
+        let index = Index::load(&index_path).unwrap();
+        assert_eq!(index.version, plainsync::core::index::INDEX_VERSION);
+        assert_eq!(index.generated_at, 1234);
+        assert_eq!(index.files.len(), 1);
+        assert!(index.imported.is_empty());
+        assert!(index.dirs.is_empty(), "Directories of old syncs are never managed");
+    }
+
     #[test]
     fn test_load_nonexistent_index() {
         let temp = TempDir::new().unwrap();
@@ -291,3 +329,399 @@ mod index_tests {
         assert_eq!(loaded[1].path, "name with spaces.txt");
     }
 }
+
+#[cfg(test)]
+mod journal_tests {
+    use super::*;
+    use plainsync::core::index::Index;
+    use plainsync::core::journal::{Journal, JournalEntry};
+
+    fn file(path: &str, size: u64) -> IndexedFile {
+        IndexedFile {
+            path: path.to_string(),
+            size,
+            mtime: 1000,
+        }
+    }
+
+    #[test]
+    fn test_journal_path_next_to_index() {
+        let journal = Journal::for_index(std::path::Path::new("/data/dev/music-1234.bin"));
+        assert_eq!(journal.path(), std::path::Path::new("/data/dev/music-1234.journal"));
+    }
+
+    #[test]
+    fn test_replay_folds_entries_into_index() {
+        let temp = TempDir::new().unwrap();
+        let index_path = temp.path().join("index.bin");
+
+        let mut index = Index::load(&index_path).unwrap();
+        index.files = vec![file("kept.txt", 10), file("old.txt", 20), file("changed.txt", 30)];
+        index.save().unwrap();
+
+        let mut journal = Journal::for_index(&index_path);
+        journal.record(&JournalEntry::Upload { file: file("new.txt", 40) }).unwrap();
+        journal.record(&JournalEntry::Upload { file: file("changed.txt", 35) }).unwrap();
+        journal.record(&JournalEntry::Delete { path: "old.txt".to_string() }).unwrap();
+
+        let mut index = Index::load(&index_path).unwrap();
+        let mut journal = Journal::for_index(&index_path);
+        assert_eq!(journal.replay(&mut index).unwrap(), 3);
+        assert!(!journal.exists(), "Journal should be cleared after replay");
+
+        let loaded = Index::load(&index_path).unwrap();
+        let mut paths: Vec<&str> = loaded.files.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["changed.txt", "kept.txt", "new.txt"]);
+        assert_eq!(loaded.files.iter().find(|f| f.path == "changed.txt").unwrap().size, 35);
+    }
+
+    #[test]
+    fn test_replay_ignores_truncated_entry() {
+        let temp = TempDir::new().unwrap();
+        let index_path = temp.path().join("index.bin");
+
+        let mut journal = Journal::for_index(&index_path);
+        journal.record(&JournalEntry::Upload { file: file("done.txt", 10) }).unwrap();
+
+        // Simulate a crash in the middle of writing the next entry
+        let mut raw = fs::read_to_string(journal.path()).unwrap();
+        raw.push_str("{\"op\":\"upload\",\"file\":{\"pa");
+        fs::write(journal.path(), raw).unwrap();
+
+        let mut index = Index::load(&index_path).unwrap();
+        let mut journal = Journal::for_index(&index_path);
+        assert_eq!(journal.replay(&mut index).unwrap(), 1);
+        assert_eq!(index.files.len(), 1);
+        assert_eq!(index.files[0].path, "done.txt");
+    }
+
+    #[test]
+    fn test_copy_markers_are_not_operations() {
+        let temp = TempDir::new().unwrap();
+        let index_path = temp.path().join("index.bin");
+
+        let mut journal = Journal::for_index(&index_path);
+        journal.record(&JournalEntry::Copying { target: "done.txt".into() }).unwrap();
+        journal.record(&JournalEntry::Upload { file: file("done.txt", 10) }).unwrap();
+        journal.record(&JournalEntry::Copying { target: "partial.txt".into() }).unwrap();
+
+        assert_eq!(journal.in_progress().unwrap(), vec!["done.txt", "partial.txt"]);
+
+        let mut index = Index::load(&index_path).unwrap();
+        assert_eq!(journal.replay(&mut index).unwrap(), 1);
+        assert_eq!(index.files.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_without_journal() {
+        let temp = TempDir::new().unwrap();
+        let index_path = temp.path().join("index.bin");
+
+        let mut index = Index::load(&index_path).unwrap();
+        let mut journal = Journal::for_index(&index_path);
+        assert_eq!(journal.replay(&mut index).unwrap(), 0);
+        assert!(!index.exists(), "Replaying nothing should not create the index");
+    }
+}
+
+#[cfg(test)]
+mod symlink_tests {
+    use super::*;
+    use plainsync::config::SymlinkPolicy;
+    use std::os::unix::fs::symlink;
+
+    /// Library with a real album, a linked album, a linked file and a loop
+    fn make_library() -> (TempDir, TempDir) {
+        let lib = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+
+        fs::create_dir(lib.path().join("Real")).unwrap();
+        fs::write(lib.path().join("Real/01.flac"), "real").unwrap();
+
+        fs::create_dir(outside.path().join("Album")).unwrap();
+        fs::write(outside.path().join("Album/02.flac"), "linked").unwrap();
+        fs::write(outside.path().join("single.flac"), "single").unwrap();
+
+        symlink(outside.path().join("Album"), lib.path().join("Linked")).unwrap();
+        symlink(outside.path().join("single.flac"), lib.path().join("single.flac")).unwrap();
+        symlink(lib.path(), lib.path().join("Real/loop")).unwrap();
+        symlink(lib.path().join("missing"), lib.path().join("broken.flac")).unwrap();
+
+        (lib, outside)
+    }
+
+    fn paths(files: Vec<IndexedFile>) -> Vec<String> {
+        let mut out: Vec<String> = files.into_iter().map(|f| f.path).collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_skip_ignores_symlinks() {
+        let (lib, _outside) = make_library();
+        let files = scanner::scan_local_with(lib.path(), SymlinkPolicy::Skip).unwrap();
+        assert_eq!(paths(files), vec!["Real/01.flac"]);
+    }
+
+    #[test]
+    fn test_follow_descends_and_detects_loops() {
+        let (lib, _outside) = make_library();
+        let files = scanner::scan_local_with(lib.path(), SymlinkPolicy::Follow).unwrap();
+        assert_eq!(paths(files), vec!["Linked/02.flac", "Real/01.flac", "single.flac"]);
+    }
+
+    #[test]
+    fn test_copy_target_copies_linked_files_only() {
+        let (lib, _outside) = make_library();
+        let files = scanner::scan_local_with(lib.path(), SymlinkPolicy::CopyTarget).unwrap();
+        let single = files.iter().find(|f| f.path == "single.flac").expect("Linked file is kept");
+        assert_eq!(single.size, 6, "Size comes from the link target");
+        assert_eq!(paths(files), vec!["Real/01.flac", "single.flac"]);
+    }
+
+    #[test]
+    fn test_special_files_are_skipped() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("song.flac"), "x").unwrap();
+        let fifo = temp.path().join("pipe");
+        let c_path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        // SAFETY: c_path is a valid NUL-terminated path
+        assert_eq!(unsafe { nix::libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        let files = scanner::scan_local(temp.path()).unwrap();
+        assert_eq!(paths(files), vec!["song.flac"]);
+    }
+}
+
+#[cfg(test)]
+mod tags_tests {
+    use super::*;
+    use plainsync::core::tags::{is_tagged_file, read_tags};
+
+    fn vorbis_block(comments: &[&str]) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend(4u32.to_le_bytes());
+        out.extend(b"test");
+        out.extend((comments.len() as u32).to_le_bytes());
+        for c in comments {
+            out.extend((c.len() as u32).to_le_bytes());
+            out.extend(c.as_bytes());
+        }
+        out
+    }
+
+    fn flac(comments: &[&str]) -> Vec<u8> {
+        let block = vorbis_block(comments);
+        let mut out = b"fLaC".to_vec();
+        out.extend([0x00, 0, 0, 34]);
+        out.extend([0u8; 34]);
+        out.push(0x84);
+        out.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        out.extend(block);
+        out
+    }
+
+    fn id3_frame(id: &str, body: &[u8]) -> Vec<u8> {
+        let mut out = id.as_bytes().to_vec();
+        out.extend((body.len() as u32).to_be_bytes());
+        out.extend([0, 0]);
+        out.extend(body);
+        out
+    }
+
+    fn mp3(frames: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = frames.concat();
+        let size = body.len() as u32;
+        let mut out = b"ID3\x03\x00\x00".to_vec();
+        out.extend([
+            (size >> 21) as u8 & 0x7f,
+            (size >> 14) as u8 & 0x7f,
+            (size >> 7) as u8 & 0x7f,
+            size as u8 & 0x7f,
+        ]);
+        out.extend(body);
+        out.extend([0xff, 0xfb, 0x90, 0x00]);
+        out
+    }
+
+    fn atom(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(body);
+        out
+    }
+
+    fn mp4_text(kind: &[u8], text: &str) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend(text.as_bytes());
+        atom(kind, &atom(b"data", &data))
+    }
+
+    fn ogg_page(seq: u32, packet: &[u8]) -> Vec<u8> {
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        let mut out = b"OggS\x00\x00".to_vec();
+        out.extend([0u8; 8]);
+        out.extend(7u32.to_le_bytes());
+        out.extend(seq.to_le_bytes());
+        out.extend([0u8; 4]);
+        out.push(lacing.len() as u8);
+        out.extend(lacing);
+        out.extend(packet);
+        out
+    }
+
+    fn write(dir: &TempDir, name: &str, content: &[u8]) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_flac_vorbis_comments() {
+        let temp = TempDir::new().unwrap();
+        let path = write(
+            &temp,
+            "a.flac",
+            &flac(&[
+                "ARTIST=Miles Davis",
+                "GENRE=Jazz",
+                "DATE=1959-08-17",
+                "TRACKNUMBER=2",
+                "RATING=80",
+            ]),
+        );
+
+        let tags = read_tags(&path).unwrap();
+        assert_eq!(tags.get("artist"), Some("Miles Davis"));
+        assert_eq!(tags.get("genre"), Some("Jazz"));
+        assert_eq!(tags.get("year"), Some("1959"));
+        assert_eq!(tags.get("track"), Some("2"));
+        assert_eq!(tags.get("rating"), Some("4"));
+    }
+
+    #[test]
+    fn test_mp3_id3v2_frames() {
+        let temp = TempDir::new().unwrap();
+
+        // UTF-16 with BOM for the artist, numeric genre, custom TXXX and POPM rating
+        let mut artist = vec![1, 0xff, 0xfe];
+        artist.extend("Björk".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        let path = write(
+            &temp,
+            "a.mp3",
+            &mp3(&[
+                id3_frame("TPE1", &artist),
+                id3_frame("TCON", b"\x00(8)"),
+                id3_frame("TYER", b"\x001997"),
+                id3_frame("TXXX", b"\x00DEVICE\x00Y1"),
+                id3_frame("POPM", b"me@example.org\x00\xff\x00\x00\x00\x01"),
+            ]),
+        );
+
+        let tags = read_tags(&path).unwrap();
+        assert_eq!(tags.get("artist"), Some("Björk"));
+        assert_eq!(tags.get("genre"), Some("Jazz"));
+        assert_eq!(tags.get("year"), Some("1997"));
+        assert_eq!(tags.get("device"), Some("Y1"));
+        assert_eq!(tags.get("rating"), Some("5"));
+    }
+
+    #[test]
+    fn test_mp4_items_after_media_data() {
+        let temp = TempDir::new().unwrap();
+        let mut trkn = vec![0, 0, 0, 0, 0, 0, 0, 0];
+        trkn.extend([0, 0, 0, 7, 0, 12, 0, 0]);
+        let mut freeform = atom(b"mean", b"\x00\x00\x00\x00com.apple.iTunes");
+        freeform.extend(atom(b"name", b"\x00\x00\x00\x00DEVICE"));
+        freeform.extend(atom(b"data", b"\x00\x00\x00\x01\x00\x00\x00\x00Y1"));
+
+        let ilst = [
+            mp4_text(b"\xa9ART", "Nina Simone"),
+            mp4_text(b"\xa9gen", "Soul"),
+            atom(b"trkn", &atom(b"data", &trkn)),
+            atom(b"----", &freeform),
+        ]
+        .concat();
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(atom(b"ilst", &ilst));
+        let moov = atom(b"moov", &atom(b"udta", &atom(b"meta", &meta)));
+
+        let file = [
+            atom(b"ftyp", b"M4A \x00\x00\x00\x00"),
+            atom(b"mdat", &[0u8; 1024]),
+            moov,
+        ]
+        .concat();
+        let path = write(&temp, "a.m4a", &file);
+
+        let tags = read_tags(&path).unwrap();
+        assert_eq!(tags.get("artist"), Some("Nina Simone"));
+        assert_eq!(tags.get("genre"), Some("Soul"));
+        assert_eq!(tags.get("track"), Some("7"));
+        assert_eq!(tags.get("device"), Some("Y1"));
+    }
+
+    fn wide_atom(kind: &[u8], size: u64) -> Vec<u8> {
+        let mut out = 1u32.to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(size.to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn test_mp4_oversized_atoms() {
+        let temp = TempDir::new().unwrap();
+
+        // A wide item size that would wrap past the end of the ilst body
+        let ilst = [
+            mp4_text(b"\xa9ART", "Nina Simone"),
+            wide_atom(b"\xa9gen", u64::MAX - 4),
+        ]
+        .concat();
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(atom(b"ilst", &ilst));
+        let moov = atom(b"moov", &atom(b"udta", &atom(b"meta", &meta)));
+        let path = write(&temp, "a.m4a", &moov);
+        let tags = read_tags(&path).unwrap();
+        assert_eq!(tags.get("artist"), Some("Nina Simone"));
+        assert_eq!(tags.get("genre"), None);
+
+        // The same at the top level, before moov is found
+        let file = [atom(b"ftyp", b"M4A \x00\x00\x00\x00"), wide_atom(b"mdat", u64::MAX - 8)].concat();
+        let path = write(&temp, "b.m4a", &file);
+        assert!(read_tags(&path).unwrap().is_empty());
+
+        // A wide header cut off by the end of the file
+        let path = write(&temp, "c.m4a", &wide_atom(b"mdat", 64)[..12]);
+        assert!(read_tags(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_opus_tags() {
+        let temp = TempDir::new().unwrap();
+        let mut comments = b"OpusTags".to_vec();
+        comments.extend(vorbis_block(&[
+            "ALBUMARTIST=Various",
+            "GENRE=Rock",
+            "GENRE=Pop",
+        ]));
+        let file = [ogg_page(0, b"OpusHead\x01\x02"), ogg_page(1, &comments)].concat();
+        let path = write(&temp, "a.opus", &file);
+
+        let tags = read_tags(&path).unwrap();
+        assert_eq!(tags.get("albumartist"), Some("Various"));
+        assert_eq!(tags.values("genre"), ["Rock", "Pop"]);
+    }
+
+    #[test]
+    fn test_untagged_and_unsupported_files() {
+        let temp = TempDir::new().unwrap();
+        let path = write(&temp, "a.mp3", b"not really audio");
+        assert!(read_tags(&path).unwrap().is_empty());
+
+        assert!(is_tagged_file("Album/01.FLAC"));
+        assert!(!is_tagged_file("Album/cover.jpg"));
+    }
+}
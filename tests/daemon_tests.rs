@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: MIT
+// tests/daemon_tests.rs
+
+//! Integration tests for the daemon control socket (protocol, server, client)
+
+use plainsync::commands::daemon::report;
+use plainsync::config::AppConfig;
+use plainsync::context::{ExecutionContext, SharedContext};
+use plainsync::daemon::client::DaemonClient;
+use plainsync::daemon::control::start_control_socket;
+use plainsync::daemon::protocol::{Event, Request, Response, SyncResult};
+use plainsync::daemon::state::DaemonState;
+use plainsync::daemon::watchdog::stalled_loop;
+use plainsync::sync::engine::SyncEngine;
+use plainsync::sync::watcher::start_watcher;
+use plainsync::utils::heartbeat::Heartbeat;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// Daemon without devices, listening in a temporary directory
+fn start_daemon(temp: &TempDir) -> (DaemonState, PathBuf) {
+    let config: AppConfig = serde_yaml::from_str("watch:\n  enabled: false\n  debounce_ms: 100\ndevices: []").unwrap();
+    let watcher = start_watcher(100, |_| {}).unwrap();
+    let state = DaemonState::new(SharedContext::new(ExecutionContext::new(config)), SyncEngine::new(), watcher);
+
+    let socket = temp.path().join("run/plainsync.sock");
+    start_control_socket(&socket, state.clone()).unwrap();
+    (state, socket)
+}
+
+#[cfg(test)]
+mod protocol_tests {
+    use super::*;
+
+    #[test]
+    fn test_request_format() {
+        let json = serde_json::to_string(&Request::Sync { device: Some("Y1".into()), folder: None, wait: false, max_bytes_per_sec: None }).unwrap();
+        assert_eq!(json, r#"{"cmd":"sync","device":"Y1","folder":null,"wait":false}"#);
+
+        let request: Request = serde_json::from_str(r#"{"cmd":"sync","folder":"Music"}"#).unwrap();
+        assert_eq!(request, Request::Sync { device: None, folder: Some("Music".into()), wait: false, max_bytes_per_sec: None });
+
+        let request: Request = serde_json::from_str(r#"{"cmd":"pause"}"#).unwrap();
+        assert_eq!(request, Request::Pause);
+    }
+
+    #[test]
+    fn test_response_and_event_format() {
+        let json = serde_json::to_string(&Response::Queued { tasks: 2 }).unwrap();
+        assert_eq!(json, r#"{"type":"queued","tasks":2}"#);
+
+        let json = serde_json::to_string(&Event::DeviceMounted { device: "Y1".into() }).unwrap();
+        assert_eq!(json, r#"{"event":"device_mounted","device":"Y1"}"#);
+    }
+}
+
+#[cfg(test)]
+mod control_tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_status_pause_and_resume() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+        let mut client = DaemonClient::connect_to(&socket).unwrap();
+
+        let status = client.status().unwrap();
+        assert!(!status.paused);
+        assert!(status.active.is_empty());
+        assert!(status.running.is_none());
+
+        client.pause().unwrap();
+        assert!(state.is_paused());
+        assert!(client.status().unwrap().paused);
+
+        client.resume().unwrap();
+        assert!(!client.status().unwrap().paused);
+    }
+
+    #[test]
+    fn test_sync_without_matching_task_fails() {
+        let temp = TempDir::new().unwrap();
+        let (_state, socket) = start_daemon(&temp);
+        let mut client = DaemonClient::connect_to(&socket).unwrap();
+
+        let err = client.sync(Some("Y1"), None, None).unwrap_err();
+        assert!(err.to_string().contains("No active sync matches"), "{}", err);
+
+        client.pause().unwrap();
+        let err = client.sync_wait(None, None, None).unwrap_err();
+        assert!(err.to_string().contains("paused"), "{}", err);
+    }
+
+    #[test]
+    fn test_subscribers_receive_events() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+        let mut events = DaemonClient::connect_to(&socket).unwrap().subscribe().unwrap();
+
+        state.on_device_mounted("Y1");
+        DaemonClient::connect_to(&socket).unwrap().pause().unwrap();
+
+        assert_eq!(events.next().unwrap().unwrap(), Event::DeviceMounted { device: "Y1".into() });
+        assert_eq!(events.next().unwrap().unwrap(), Event::Paused);
+    }
+
+    #[test]
+    fn test_invalid_request_gets_error() {
+        let temp = TempDir::new().unwrap();
+        let (_state, socket) = start_daemon(&temp);
+
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        stream.write_all(b"{\"cmd\":\"reboot\"}\n{\"cmd\":\"status\"}\n").unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with(r#"{"type":"error""#), "{}", line);
+
+        // The connection stays usable
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with(r#"{"type":"status""#), "{}", line);
+    }
+
+    #[test]
+    fn test_no_sync_after_shutdown() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+
+        state.shutdown();
+        assert!(state.is_stopping());
+
+        let err = DaemonClient::connect_to(&socket).unwrap().sync(None, None, None).unwrap_err();
+        assert!(err.to_string().contains("shutting down"), "{}", err);
+    }
+
+    #[test]
+    fn test_second_daemon_refused() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+
+        let err = start_control_socket(&socket, state).unwrap_err();
+        assert!(err.to_string().contains("already listening"), "{}", err);
+    }
+
+    #[test]
+    fn test_socket_directory_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+
+        // Created closed to others, refused when others can enter it
+        let mode = std::fs::metadata(socket.parent().unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let shared = temp.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let err = start_control_socket(&shared.join("plainsync.sock"), state).unwrap_err();
+        assert!(err.to_string().contains("mode 700"), "{}", err);
+        assert!(!shared.join("plainsync.sock").exists());
+    }
+
+    #[test]
+    fn test_client_refuses_socket_of_another_user() {
+        // Only root can hand a socket over to another user
+        if users::get_current_uid() != 0 {
+            return;
+        }
+        let temp = TempDir::new().unwrap();
+        let (_state, socket) = start_daemon(&temp);
+        std::os::unix::fs::chown(&socket, Some(65534), None).unwrap();
+
+        let err = DaemonClient::connect_to(&socket).err().unwrap();
+        assert!(err.to_string().contains("another user"), "{}", err);
+    }
+}
+
+#[cfg(test)]
+mod command_tests {
+    use super::*;
+
+    fn result(device: &str, error: Option<&str>) -> SyncResult {
+        SyncResult {
+            device: device.into(),
+            folder: "Music".into(),
+            finished: 0,
+            uploaded: 1,
+            deleted: 0,
+            upload_bytes: 10,
+            delete_bytes: 0,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_report_fails_when_a_daemon_sync_failed() {
+        assert!(report(&[result("Y1", None)]).is_ok());
+
+        let err = report(&[result("Y1", None), result("SD", Some("device full"))]).unwrap_err();
+        assert_eq!(err.to_string(), "1 sync(s) failed in the daemon");
+    }
+
+    #[test]
+    fn test_sync_request_carries_bandwidth_override() {
+        let request: Request = serde_json::from_str(r#"{"cmd":"sync","device":"Y1","wait":true,"max_bytes_per_sec":1000}"#).unwrap();
+        assert_eq!(
+            request,
+            Request::Sync { device: Some("Y1".into()), folder: None, wait: true, max_bytes_per_sec: Some(1000) }
+        );
+    }
+}
+
+#[cfg(test)]
+mod reload_tests {
+    use super::*;
+    use std::fs;
+
+    const CONFIG: &str = "watch:\n  enabled: false\n  debounce_ms: 100\ndevices:\n  - name: Y1\n    label: null\n    mount: /nonexistent\n    mountinfo: false\n    folders: []\n";
+
+    #[test]
+    fn test_reload_swaps_context() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+        let mut events = DaemonClient::connect_to(&socket).unwrap().subscribe().unwrap();
+
+        let path = temp.path().join("config.yaml");
+        fs::write(&path, CONFIG).unwrap();
+        state.reload_config(&path).unwrap();
+
+        assert_eq!(state.context().config.devices[0].name, "Y1");
+        assert_eq!(events.next().unwrap().unwrap(), Event::ConfigReloaded);
+    }
+
+    #[test]
+    fn test_reload_syncs_changed_folders_only() {
+        let temp = TempDir::new().unwrap();
+        let (state, _socket) = start_daemon(&temp);
+        // Queued syncs are kept aside instead of running
+        state.pause();
+
+        let mount = temp.path().join("device");
+        fs::create_dir_all(&mount).unwrap();
+        let folder = |target: &str, mtime: bool| {
+            format!(
+                "      - source: {}\n        target: {}\n        enabled: true\n        preserve:\n          mtime: {}\n",
+                temp.path().join(target).display(), target, mtime
+            )
+        };
+        let config = |folders: &str| {
+            CONFIG.replace("/nonexistent", &mount.display().to_string()).replace("folders: []\n", &format!("folders:\n{}", folders))
+        };
+
+        let path = temp.path().join("config.yaml");
+        fs::write(&path, config(&[folder("A", true), folder("B", true)].concat())).unwrap();
+        assert_eq!(state.reload_config(&path).unwrap(), 2);
+
+        // Saving the file again changes nothing
+        assert_eq!(state.reload_config(&path).unwrap(), 0);
+
+        fs::write(&path, config(&[folder("A", true), folder("B", false)].concat())).unwrap();
+        assert_eq!(state.reload_config(&path).unwrap(), 1);
+
+        // The syncs run on another thread, deferred while paused
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while state.status().deferred < 2 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(state.status().deferred, 2);
+    }
+
+    #[test]
+    fn test_invalid_config_keeps_current_one() {
+        let temp = TempDir::new().unwrap();
+        let (state, socket) = start_daemon(&temp);
+        let mut events = DaemonClient::connect_to(&socket).unwrap().subscribe().unwrap();
+
+        let path = temp.path().join("config.yaml");
+        fs::write(&path, "devices: [oops").unwrap();
+        let err = state.reload_config(&path).unwrap_err();
+        assert!(err.to_string().contains("Invalid YAML"), "{}", err);
+
+        // Unknown profile: valid YAML, invalid configuration
+        fs::write(&path, CONFIG.replace("mountinfo: false", "mountinfo: false\n    profile: walkman")).unwrap();
+        assert!(state.reload_config(&path).is_err());
+
+        assert!(state.context().config.devices.is_empty());
+        assert!(matches!(events.next().unwrap().unwrap(), Event::ConfigError { .. }));
+        assert!(matches!(events.next().unwrap().unwrap(), Event::ConfigError { .. }));
+    }
+}
+
+#[cfg(test)]
+mod watchdog_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_status_line_follows_state() {
+        let temp = TempDir::new().unwrap();
+        let (state, _socket) = start_daemon(&temp);
+
+        assert_eq!(state.status_line(), "Idle, 0 devices mounted");
+        state.pause();
+        assert_eq!(state.status_line(), "Paused, 0 devices mounted");
+        state.shutdown();
+        assert!(state.status_line().starts_with("Stopping"));
+    }
+
+    #[test]
+    fn test_stalled_loop_detection() {
+        let timeout = Duration::from_millis(50);
+        let loops = vec![("watcher", Heartbeat::new()), ("detect", Heartbeat::new())];
+        assert_eq!(stalled_loop(&loops, timeout, None), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        loops[0].1.beat();
+        assert_eq!(stalled_loop(&loops, timeout, None), Some("detect"));
+
+        // A sync that keeps moving holds its loop: not stalled
+        assert_eq!(stalled_loop(&loops, timeout, Some(Duration::from_millis(10))), None);
+        assert_eq!(stalled_loop(&loops, timeout, Some(Duration::from_millis(60))), Some("detect"));
+    }
+
+    #[test]
+    fn test_long_copy_keeps_its_loop_alive() {
+        use plainsync::config::PreserveConfig;
+        use plainsync::utils::fs::{copy_file, CopyOptions};
+
+        // One throttled file takes longer than the timeout: 1 MiB at 2 MiB/s
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("big.flac");
+        std::fs::write(&src, vec![0u8; 1024 * 1024]).unwrap();
+        let timeout = Duration::from_millis(200);
+
+        let detect = Heartbeat::new();
+        let activity = Heartbeat::new();
+        let copy = {
+            let (activity, dst) = (activity.clone(), temp.path().join("copy.flac"));
+            std::thread::spawn(move || {
+                let mut opts = CopyOptions::new(Some(2 * 1024 * 1024), PreserveConfig::default());
+                activity.around(|| copy_file(&src, &dst, &mut opts))
+            })
+        };
+
+        // The detect loop is busy copying and does not beat, the copy chunks do
+        let loops = vec![("detect", detect.clone())];
+        while !copy.is_finished() {
+            assert_eq!(stalled_loop(&loops, timeout, Some(activity.age())), None);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        copy.join().unwrap().unwrap();
+        assert!(detect.age() >= timeout, "the copy outlasted the timeout");
+
+        // Once nothing moves any more, the loop is stalled
+        std::thread::sleep(timeout);
+        assert_eq!(stalled_loop(&loops, timeout, Some(activity.age())), Some("detect"));
+    }
+}
@@ -1,4 +1,4 @@
-/// Tests for the refactored modules
+//! Tests for the refactored modules
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,1520 @@
+// SPDX-License-Identifier: MIT
+// tests/sync_tests.rs
+
+//! Integration tests for folder synchronization (sync_folder, preview_folder)
+
+use plainsync::config::DeviceConfig;
+use plainsync::core::index::Index;
+use plainsync::core::task::SyncTask;
+use plainsync::sync::run::{preview_folder, sync_folder};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Build a push task from `src` to `dst`, with its index stored in `data`.
+fn make_task(src: &Path, dst: &Path, data: &Path) -> SyncTask {
+    let yaml = format!(
+        r#"
+name: TEST
+label: null
+mount: "{}"
+mountinfo: false
+folders:
+  - source: "{}"
+    target: "Music"
+    enabled: true
+"#,
+        dst.display(),
+        src.display(),
+    );
+    let device: DeviceConfig = serde_yaml::from_str(&yaml).unwrap();
+    let folder = device.folders[0].clone();
+
+    SyncTask {
+        device,
+        folder,
+        index_path: data.join("music.bin"),
+        mount: dst.to_path_buf(),
+        source: src.to_path_buf(),
+        target: dst.to_path_buf(),
+        mode: plainsync::config::SyncMode::Push,
+        max_bytes_per_sec: None,
+        transcode_cache: data.join("transcode"),
+        library: None,
+    }
+}
+
+/// Minimal FLAC file holding only Vorbis comments
+fn flac(comments: &[&str]) -> Vec<u8> {
+    let mut block = vec![];
+    block.extend(0u32.to_le_bytes());
+    block.extend((comments.len() as u32).to_le_bytes());
+    for c in comments {
+        block.extend((c.len() as u32).to_le_bytes());
+        block.extend(c.as_bytes());
+    }
+    let mut out = b"fLaC".to_vec();
+    out.push(0x84);
+    out.extend(&(block.len() as u32).to_be_bytes()[1..]);
+    out.extend(block);
+    out
+}
+
+#[cfg(test)]
+mod sync_folder_tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_copies_and_indexes() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(src.path().join("Album")).unwrap();
+        fs::write(src.path().join("Album/01.flac"), "one").unwrap();
+        fs::write(src.path().join("Album/02.flac"), "two").unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(dst.path().join("Album/01.flac")).unwrap(), "one");
+        assert_eq!(fs::read_to_string(dst.path().join("Album/02.flac")).unwrap(), "two");
+        assert_eq!(Index::load(&task.index_path).unwrap().files.len(), 2);
+    }
+
+    #[test]
+    fn test_sync_deletes_removed_files() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("a.mp3"), "a").unwrap();
+        fs::write(src.path().join("b.mp3"), "b").unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+
+        fs::remove_file(src.path().join("b.mp3")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(dst.path().join("a.mp3").exists());
+        assert!(!dst.path().join("b.mp3").exists());
+    }
+}
+
+#[cfg(test)]
+mod dry_run_tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_does_not_touch_target_or_index() {
+        let (src, data) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let dst = data.path().join("device");
+        fs::write(src.path().join("a.mp3"), "aaaa").unwrap();
+        fs::write(src.path().join("b.mp3"), "bb").unwrap();
+
+        let task = make_task(src.path(), &dst, data.path());
+        let stats = preview_folder(&task).unwrap();
+
+        assert_eq!(stats.upload_count, 2);
+        assert_eq!(stats.upload_bytes, 6);
+        assert!(!dst.exists(), "Dry run must not create the target");
+        assert!(!task.index_path.exists(), "Dry run must not write the index");
+    }
+
+    #[test]
+    fn test_preview_reports_deletes() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("a.mp3"), "a").unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+        fs::remove_file(src.path().join("a.mp3")).unwrap();
+
+        let stats = preview_folder(&task).unwrap();
+        assert_eq!(stats.delete_count, 1);
+        assert!(dst.path().join("a.mp3").exists(), "Dry run must not delete anything");
+    }
+}
+
+#[cfg(test)]
+mod status_tests {
+    use super::*;
+    use plainsync::commands::status::status_row;
+    use plainsync::core::index::IndexedFile;
+    use plainsync::core::journal::{Journal, JournalEntry};
+
+    #[test]
+    fn test_status_row_reports_pending_and_interrupted_sync() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let task = make_task(src.path(), dst.path(), data.path());
+
+        fs::write(src.path().join("a.mp3"), "a").unwrap();
+        fs::write(src.path().join("b.mp3"), "bb").unwrap();
+        let modified = fs::metadata(src.path().join("a.mp3")).unwrap().modified().unwrap();
+        let mtime = modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+
+        // Fixture: index with one synced file, journal of a run that uploaded a.mp3
+        let mut idx = Index::load(&task.index_path).unwrap();
+        idx.files = vec![IndexedFile { path: "old.mp3".into(), size: 3, mtime: 1 }];
+        idx.save().unwrap();
+        let mut journal = Journal::for_index(&task.index_path);
+        journal.record(&JournalEntry::Upload { file: IndexedFile { path: "a.mp3".into(), size: 1, mtime } }).unwrap();
+
+        let [folder, mode, last_sync, uploads, deletes, unsupported] = status_row(&task, true).unwrap();
+        assert_eq!(folder, "Music");
+        assert_eq!(mode, "Push");
+        assert!(last_sync.ends_with("(interrupted, 1 operation(s) done)"), "{}", last_sync);
+        assert_eq!(uploads, "1 (+2 B)");
+        assert_eq!(deletes, "1 (-3 B)");
+        assert_eq!(unsupported, "0");
+
+        // Read-only: the journal is still there for the next run
+        assert!(journal.exists());
+    }
+
+    #[test]
+    fn test_status_row_never_synced() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let task = make_task(src.path(), dst.path(), data.path());
+
+        let row = status_row(&task, true).unwrap();
+        assert_eq!(row[2], "never");
+        assert_eq!(row[3], "0 (+0 B)");
+    }
+}
+
+#[cfg(test)]
+mod selector_tests {
+    use super::*;
+    use plainsync::core::task::{SyncTaskFilter, TaskSelector};
+
+    fn tagged(device: &str, target: &str, tags: &[&str]) -> SyncTask {
+        let tmp = Path::new("/tmp");
+        let mut task = make_task(tmp, tmp, tmp);
+        task.device.name = device.to_string();
+        task.folder.target = target.to_string();
+        task.folder.tags = tags.iter().map(|t| t.to_string()).collect();
+        task
+    }
+
+    fn plan() -> Vec<SyncTask> {
+        vec![
+            tagged("Y1", "Music", &["music"]),
+            tagged("Y1", "Podcasts", &["podcasts", "daily"]),
+            tagged("Phone", "Podcasts", &["podcasts"]),
+        ]
+    }
+
+    fn targets(tasks: &[SyncTask]) -> Vec<String> {
+        tasks.iter().map(|t| format!("{}:{}", t.device.name, t.folder.target)).collect()
+    }
+
+    #[test]
+    fn test_empty_selector_keeps_everything() {
+        let selector = TaskSelector::default();
+        assert!(selector.is_empty());
+        assert_eq!(plan().filter_selected(&selector).len(), 3);
+    }
+
+    #[test]
+    fn test_select_by_device_and_folder() {
+        let selector = TaskSelector {
+            devices: vec!["Y1".into()],
+            folders: vec!["Podcasts".into()],
+            ..Default::default()
+        };
+        assert_eq!(targets(&plan().filter_selected(&selector)), vec!["Y1:Podcasts"]);
+    }
+
+    #[test]
+    fn test_select_by_tag() {
+        let selector = TaskSelector { tags: vec!["podcasts".into()], ..Default::default() };
+        assert_eq!(targets(&plan().filter_selected(&selector)), vec!["Y1:Podcasts", "Phone:Podcasts"]);
+    }
+
+    #[test]
+    fn test_excludes_win_over_includes() {
+        let selector = TaskSelector {
+            tags: vec!["podcasts".into()],
+            exclude_tags: vec!["daily".into()],
+            exclude_devices: vec!["Nope".into()],
+            ..Default::default()
+        };
+        assert_eq!(targets(&plan().filter_selected(&selector)), vec!["Phone:Podcasts"]);
+
+        let selector = TaskSelector { exclude_folders: vec!["Music".into()], ..Default::default() };
+        assert_eq!(plan().filter_selected(&selector).len(), 2);
+    }
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+    use plainsync::config::SyncMode;
+    use plainsync::core::index::IndexedFile;
+    use plainsync::sync::import::render_template;
+    use std::fs::FileTimes;
+    use std::time::{Duration, SystemTime};
+
+    fn import_task(src: &Path, dst: &Path, data: &Path, template: &str) -> SyncTask {
+        let mut task = make_task(src, dst, data);
+        task.mode = SyncMode::Import;
+        task.folder.template = Some(template.to_string());
+        task
+    }
+
+    fn write_at(path: &Path, content: &str, mtime: i64) {
+        fs::write(path, content).unwrap();
+        let when = SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64);
+        fs::File::options().write(true).open(path).unwrap()
+            .set_times(FileTimes::new().set_modified(when)).unwrap();
+    }
+
+    #[test]
+    fn test_render_template() {
+        let file = IndexedFile { path: "DCIM/100/IMG_01.JPG".to_string(), size: 1, mtime: 1_700_000_000 };
+        let rendered = render_template("{year}/{stem}.{ext}", &file);
+        assert_eq!(rendered, Path::new("2023/IMG_01.JPG"));
+
+        let rendered = render_template("{dir}/{name}", &file);
+        assert_eq!(rendered, Path::new("DCIM/100/IMG_01.JPG"));
+    }
+
+    #[test]
+    fn test_import_uses_template_and_keeps_device_files() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        write_at(&dev.path().join("REC001.WAV"), "voice", 1_700_000_000);
+
+        let task = import_task(dev.path(), host.path(), data.path(), "{year}/{name}");
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(host.path().join("2023/REC001.WAV")).unwrap(), "voice");
+        assert!(dev.path().join("REC001.WAV").exists(), "Import must not touch the device");
+        assert_eq!(Index::load(&task.index_path).unwrap().imported.len(), 1);
+    }
+
+    #[test]
+    fn test_import_skips_moved_and_duplicate_files() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        write_at(&dev.path().join("REC001.WAV"), "voice", 1_700_000_000);
+
+        let task = import_task(dev.path(), host.path(), data.path(), "{name}");
+        sync_folder(&task, false, false).unwrap();
+
+        // User renames the imported file on the host
+        fs::rename(host.path().join("REC001.WAV"), host.path().join("meeting.wav")).unwrap();
+
+        // Same content reappears on the device under another name
+        write_at(&dev.path().join("REC002.WAV"), "voice", 1_700_000_100);
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(!host.path().join("REC001.WAV").exists(), "Renamed file must not be imported again");
+        assert!(!host.path().join("REC002.WAV").exists(), "Duplicate content must be skipped");
+    }
+
+    #[test]
+    fn test_import_does_not_overwrite_name_collisions() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(dev.path().join("A")).unwrap();
+        fs::create_dir(dev.path().join("B")).unwrap();
+        write_at(&dev.path().join("A/IMG.JPG"), "first", 1_700_000_000);
+        write_at(&dev.path().join("B/IMG.JPG"), "second", 1_700_000_000);
+
+        let task = import_task(dev.path(), host.path(), data.path(), "{name}");
+        sync_folder(&task, false, false).unwrap();
+
+        let mut contents = vec![
+            fs::read_to_string(host.path().join("IMG.JPG")).unwrap(),
+            fs::read_to_string(host.path().join("IMG (1).JPG")).unwrap(),
+        ];
+        contents.sort();
+        assert_eq!(contents, vec!["first", "second"]);
+    }
+}
+
+#[cfg(test)]
+mod move_tests {
+    use super::*;
+    use plainsync::config::SyncMode;
+
+    fn move_task(src: &Path, dst: &Path, data: &Path, delete_after_days: Option<u32>) -> SyncTask {
+        let mut task = make_task(src, dst, data);
+        task.mode = SyncMode::Move;
+        task.folder.delete_after_days = delete_after_days;
+        task
+    }
+
+    #[test]
+    fn test_move_deletes_verified_originals() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(dev.path().join("FOLDER01")).unwrap();
+        fs::write(dev.path().join("FOLDER01/REC001.WAV"), "voice").unwrap();
+
+        let task = move_task(dev.path(), host.path(), data.path(), None);
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(host.path().join("FOLDER01/REC001.WAV")).unwrap(), "voice");
+        assert!(!dev.path().join("FOLDER01/REC001.WAV").exists(), "Original should be deleted");
+        assert!(Index::load(&task.index_path).unwrap().files.is_empty());
+    }
+
+    #[test]
+    fn test_move_keeps_recent_originals_until_old_enough() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(dev.path().join("REC001.WAV"), "voice").unwrap();
+
+        let task = move_task(dev.path(), host.path(), data.path(), Some(7));
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(host.path().join("REC001.WAV").exists());
+        assert!(dev.path().join("REC001.WAV").exists(), "Fresh file must stay on the device");
+
+        // Second run: nothing new to copy, still too recent to delete
+        sync_folder(&task, false, false).unwrap();
+        assert!(dev.path().join("REC001.WAV").exists());
+        assert!(!host.path().join("REC001 (1).WAV").exists(), "File must not be copied twice");
+
+        // Once old enough, the next run deletes it
+        let task = move_task(dev.path(), host.path(), data.path(), Some(0));
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dev.path().join("REC001.WAV").exists());
+    }
+
+    #[test]
+    fn test_move_keeps_original_when_host_copy_changed() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(dev.path().join("REC001.WAV"), "voice").unwrap();
+
+        let task = move_task(dev.path(), host.path(), data.path(), Some(7));
+        sync_folder(&task, false, false).unwrap();
+
+        // The host copy is edited before the delayed delete is due
+        fs::write(host.path().join("REC001.WAV"), "edited").unwrap();
+
+        let task = move_task(dev.path(), host.path(), data.path(), Some(0));
+        sync_folder(&task, false, false).unwrap();
+        assert!(dev.path().join("REC001.WAV").exists(), "Only copy left must stay on the device");
+    }
+
+    #[test]
+    fn test_move_checks_host_copy_of_duplicates() {
+        let (dev, host, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(dev.path().join("REC001.WAV"), "voice").unwrap();
+
+        let task = move_task(dev.path(), host.path(), data.path(), None);
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dev.path().join("REC001.WAV").exists());
+
+        // Same content reappears on the device: skipped as already imported
+        fs::write(dev.path().join("REC002.WAV"), "voice").unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!host.path().join("REC002.WAV").exists(), "Duplicate is not copied");
+        assert!(!dev.path().join("REC002.WAV").exists(), "Host copy checked, duplicate deleted");
+
+        // Once the host copy is gone, a duplicate is the only copy left
+        fs::remove_file(host.path().join("REC001.WAV")).unwrap();
+        fs::write(dev.path().join("REC003.WAV"), "voice").unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(dev.path().join("REC003.WAV").exists(), "Duplicate of a lost copy must be kept");
+    }
+}
+
+#[cfg(test)]
+mod prune_tests {
+    use super::*;
+    use plainsync::core::index::parent_dirs;
+
+    #[test]
+    fn test_parent_dirs() {
+        assert_eq!(parent_dirs("a/b/c.flac"), vec!["a", "a/b"]);
+        assert!(parent_dirs("c.flac").is_empty());
+    }
+
+    #[test]
+    fn test_deleting_album_prunes_empty_managed_dirs() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album")).unwrap();
+        fs::write(src.path().join("Artist/Album/01.flac"), "one").unwrap();
+        fs::write(src.path().join("keep.flac"), "keep").unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Artist/Album").is_dir());
+
+        fs::remove_dir_all(src.path().join("Artist")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(!dst.path().join("Artist").exists(), "Emptied artist folder should be pruned");
+        assert!(dst.path().join("keep.flac").exists());
+        assert!(Index::load(&task.index_path).unwrap().dirs.is_empty());
+    }
+
+    #[test]
+    fn test_user_content_and_dirs_are_kept() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album")).unwrap();
+        fs::write(src.path().join("Artist/Album/01.flac"), "one").unwrap();
+        fs::create_dir_all(dst.path().join("Recordings")).unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+
+        // The user drops a file of their own next to the managed album
+        fs::write(dst.path().join("Artist/cover.jpg"), "user").unwrap();
+
+        fs::remove_dir_all(src.path().join("Artist")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(!dst.path().join("Artist/Album").exists(), "Empty managed album is pruned");
+        assert!(dst.path().join("Artist/cover.jpg").exists(), "Non-empty directory is kept");
+        assert!(dst.path().join("Recordings").is_dir(), "Unmanaged empty directory is kept");
+    }
+
+    #[test]
+    fn test_existing_parent_dirs_are_not_managed() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album")).unwrap();
+        fs::write(src.path().join("Artist/Album/01.flac"), "one").unwrap();
+
+        // The user created the artist folder before the first sync
+        fs::create_dir_all(dst.path().join("Artist")).unwrap();
+
+        let task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(Index::load(&task.index_path).unwrap().dirs, vec!["Artist/Album"]);
+
+        fs::remove_dir_all(src.path().join("Artist")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(!dst.path().join("Artist/Album").exists(), "Created album folder is pruned");
+        assert!(dst.path().join("Artist").is_dir(), "Pre-existing folder is kept even when empty");
+    }
+}
+
+#[cfg(test)]
+mod transcode_tests {
+    use super::*;
+    use plainsync::config::TranscodeRule;
+    use plainsync::sync::run::pending_diff;
+
+    fn rule(to: &str, command: &str) -> TranscodeRule {
+        TranscodeRule { from: "flac".into(), to: to.into(), command: command.into() }
+    }
+
+    fn transcode_task(src: &Path, dst: &Path, data: &Path, rules: Vec<TranscodeRule>) -> SyncTask {
+        let mut task = make_task(src, dst, data);
+        task.folder.transcode = rules;
+        task
+    }
+
+    fn cache_entries(task: &SyncTask) -> usize {
+        fs::read_dir(&task.transcode_cache).map(|d| d.count()).unwrap_or(0)
+    }
+
+    #[test]
+    fn test_transcoded_upload_rewrites_extension() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(src.path().join("Album")).unwrap();
+        fs::write(src.path().join("Album/01.flac"), "lossless").unwrap();
+        fs::write(src.path().join("Album/cover.jpg"), "cover").unwrap();
+
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "cp {input} {output}")]);
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(dst.path().join("Album/01.opus")).unwrap(), "lossless");
+        assert!(!dst.path().join("Album/01.flac").exists());
+        assert!(dst.path().join("Album/cover.jpg").exists(), "Files without a rule are copied as is");
+
+        let idx = Index::load(&task.index_path).unwrap();
+        assert_eq!(idx.target_path("Album/01.flac"), "Album/01.opus");
+        assert_eq!(cache_entries(&task), 1);
+
+        // Unchanged sources and settings: nothing to do
+        assert!(pending_diff(&task).unwrap().0.to_upload.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_transcodes_of_same_file() {
+        use plainsync::sync::transcode::Transcoder;
+        use plainsync::core::index::IndexedFile;
+        use std::collections::BTreeMap;
+
+        let (src, data) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+        let file = IndexedFile { path: "01.flac".into(), size: 8, mtime: 1 };
+        // Slow command: both syncs are transcoding at the same time
+        let slow = rule("opus", "sh -c cp${IFS}\"$0\"${IFS}\"$1\";sleep${IFS}0.3 {input} {output}");
+        let cache = data.path().join("transcode");
+        let targets = BTreeMap::new();
+
+        let outputs: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| {
+                    let transcoder = Transcoder { source: src.path(), rules: &[], cache: &cache, targets: &targets };
+                    transcoder.cached_output(&file, &slow).unwrap()
+                }))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(fs::read_to_string(&outputs[0]).unwrap(), "lossless");
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1, "No partial output left behind");
+    }
+
+    #[test]
+    fn test_clean_cache() {
+        use plainsync::sync::transcode::clean_cache;
+        use std::time::Duration;
+
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "cp {input} {output}")]);
+        sync_folder(&task, false, false).unwrap();
+
+        // Fresh outputs are kept by an age limit, removed without one
+        assert_eq!(clean_cache(&task.transcode_cache, Some(Duration::from_secs(3600))).unwrap(), (0, 0));
+        assert_eq!(clean_cache(&task.transcode_cache, None).unwrap(), (1, 8));
+        assert_eq!(cache_entries(&task), 0);
+        assert!(dst.path().join("01.opus").exists(), "Cleaning the cache never touches devices");
+    }
+
+    #[test]
+    fn test_settings_change_retranscodes() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "cp {input} {output}")]);
+        sync_folder(&task, false, false).unwrap();
+
+        // Same output format, other command (e.g. bitrate)
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "cp -f {input} {output}")]);
+        assert_eq!(pending_diff(&task).unwrap().0.to_upload.len(), 1);
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(cache_entries(&task), 2);
+        assert!(dst.path().join("01.opus").exists());
+
+        // Other output format: the previous output is replaced
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("mp3", "cp {input} {output}")]);
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("01.mp3").exists());
+        assert!(!dst.path().join("01.opus").exists());
+
+        // Rule removed: the source is copied as is again
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![]);
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("01.flac").exists());
+        assert!(!dst.path().join("01.mp3").exists());
+        assert!(Index::load(&task.index_path).unwrap().transcoded.is_empty());
+    }
+
+    #[test]
+    fn test_deleted_source_removes_output() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "cp {input} {output}")]);
+        sync_folder(&task, false, false).unwrap();
+
+        fs::remove_file(src.path().join("01.flac")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(!dst.path().join("01.opus").exists());
+        assert!(Index::load(&task.index_path).unwrap().transcoded.is_empty());
+    }
+
+    #[test]
+    fn test_cache_is_shared_between_devices() {
+        let (src, data) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let (dev1, dev2) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+
+        let rules = vec![rule("opus", "cp {input} {output}")];
+        let task1 = transcode_task(src.path(), dev1.path(), data.path(), rules.clone());
+        sync_folder(&task1, false, false).unwrap();
+
+        // Mark the cached output to see it reused instead of transcoded again
+        let cached = fs::read_dir(&task1.transcode_cache).unwrap().next().unwrap().unwrap().path();
+        fs::write(&cached, "from cache").unwrap();
+
+        let mut task2 = transcode_task(src.path(), dev2.path(), data.path(), rules);
+        task2.index_path = data.path().join("second.bin");
+        sync_folder(&task2, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(dev2.path().join("01.opus")).unwrap(), "from cache");
+    }
+
+    #[test]
+    fn test_failed_command_leaves_no_output() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "lossless").unwrap();
+
+        let task = transcode_task(src.path(), dst.path(), data.path(), vec![rule("opus", "false {input} {output}")]);
+        assert!(sync_folder(&task, false, false).is_err());
+
+        assert!(!dst.path().join("01.opus").exists());
+        assert_eq!(cache_entries(&task), 0);
+    }
+}
+
+#[cfg(test)]
+mod hooks_tests {
+    use super::*;
+    use plainsync::sync::hooks::{run_device_hooks, HookEvent};
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_post_sync_receives_stats() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "one").unwrap();
+        let out = data.path().join("env.txt");
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.hooks.post_sync = Some(format!("env | grep ^PLAINSYNC_ > '{}'", out.display()));
+        sync_folder(&task, false, false).unwrap();
+
+        let env = read(&out);
+        assert!(env.contains("PLAINSYNC_EVENT=post_sync"));
+        assert!(env.contains("PLAINSYNC_DEVICE=TEST"));
+        assert!(env.contains("PLAINSYNC_FOLDER=Music"));
+        assert!(env.contains("PLAINSYNC_UPLOADS=1"));
+        assert!(env.contains("PLAINSYNC_UPLOAD_BYTES=3"));
+        assert!(env.contains("PLAINSYNC_EXIT_STATUS=0"));
+    }
+
+    #[test]
+    fn test_failing_pre_sync_aborts_task() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("01.flac"), "one").unwrap();
+        let out = data.path().join("error.txt");
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.hooks.pre_sync = Some("exit 3".into());
+        task.folder.hooks.on_error = Some(format!(
+            "echo \"$PLAINSYNC_EXIT_STATUS $PLAINSYNC_ERROR\" > '{}'",
+            out.display()
+        ));
+
+        assert!(sync_folder(&task, false, false).is_err());
+        assert!(!dst.path().join("01.flac").exists(), "Nothing is copied");
+        assert!(read(&out).starts_with("1 pre_sync hook failed"), "on_error got: {}", read(&out));
+    }
+
+    #[test]
+    fn test_device_hooks_run_before_folder_hooks() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let log = data.path().join("log.txt");
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.hooks.pre_sync = Some(format!("echo device >> '{}'", log.display()));
+        task.folder.hooks.pre_sync = Some(format!("echo folder >> '{}'", log.display()));
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(read(&log), "device\nfolder\n");
+    }
+
+    #[test]
+    fn test_mount_hooks() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let log = data.path().join("log.txt");
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.hooks.on_mount = Some(format!("echo \"$PLAINSYNC_EVENT $PLAINSYNC_MOUNT\" >> '{}'", log.display()));
+        task.device.folders[0].hooks.on_unmount = Some(format!("echo \"$PLAINSYNC_EVENT $PLAINSYNC_FOLDER\" >> '{}'", log.display()));
+
+        run_device_hooks(&task.device, dst.path(), HookEvent::Mount);
+        run_device_hooks(&task.device, dst.path(), HookEvent::Unmount);
+
+        assert_eq!(read(&log), format!("on_mount {}\non_unmount Music\n", dst.path().display()));
+    }
+}
+
+#[cfg(test)]
+mod playlist_tests {
+    use super::*;
+    use plainsync::config::{PlaylistConfig, PlaylistEncoding, PlaylistPaths};
+    use plainsync::sync::playlists::{plan_playlists, render};
+
+    fn tracks(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_plan_album_top_level_and_all() {
+        let cfg = PlaylistConfig { top_level: true, all_tracks: true, ..Default::default() };
+        let plan = plan_playlists(&cfg, "Music", &tracks(&[
+            "Artist/Album/02.mp3",
+            "Artist/Album/01.mp3",
+            "Artist/Album/cover.jpg",
+            "loose.mp3",
+        ]));
+
+        assert_eq!(plan["Artist/Album/Album.m3u8"], vec!["01.mp3", "02.mp3"]);
+        assert_eq!(plan["Artist.m3u8"], vec!["Artist/Album/01.mp3", "Artist/Album/02.mp3"]);
+        assert_eq!(plan["All tracks.m3u8"].len(), 3);
+        assert_eq!(plan.len(), 3);
+    }
+
+    #[test]
+    fn test_absolute_paths_separator_and_latin1() {
+        let cfg = PlaylistConfig {
+            paths: PlaylistPaths::Absolute,
+            separator: "\\".into(),
+            encoding: PlaylistEncoding::Latin1,
+            ..Default::default()
+        };
+        let plan = plan_playlists(&cfg, "Music", &tracks(&["Björk/Début/01.mp3"]));
+        let (name, entries) = plan.iter().next().unwrap();
+
+        assert_eq!(name, "Björk/Début/Début.m3u");
+        let bytes = render(entries, &cfg.separator, cfg.encoding);
+        assert_eq!(bytes, b"#EXTM3U\n\\Music\\Bj\xF6rk\\D\xE9but\\01.mp3\n".to_vec());
+    }
+
+    #[test]
+    fn test_playlists_follow_sync_without_extra_writes() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album")).unwrap();
+        fs::write(src.path().join("Artist/Album/01.mp3"), "one").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.playlists = Some(PlaylistConfig::default());
+        sync_folder(&task, false, false).unwrap();
+
+        let playlist = dst.path().join("Artist/Album/Album.m3u8");
+        assert_eq!(fs::read_to_string(&playlist).unwrap(), "#EXTM3U\n01.mp3\n");
+        let written_at = fs::metadata(&playlist).unwrap().modified().unwrap();
+
+        // Unrelated change elsewhere: the playlist is left untouched
+        fs::write(src.path().join("notes.txt"), "x").unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(fs::metadata(&playlist).unwrap().modified().unwrap(), written_at);
+
+        // Album removed: its playlist and directories go too
+        fs::remove_dir_all(src.path().join("Artist")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dst.path().join("Artist").exists());
+        assert!(Index::load(&task.index_path).unwrap().playlists.is_empty());
+    }
+
+    #[test]
+    fn test_disabling_playlists_removes_them() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(src.path().join("Album")).unwrap();
+        fs::write(src.path().join("Album/01.mp3"), "one").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.playlists = Some(PlaylistConfig::default());
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Album/Album.m3u8").exists());
+
+        task.folder.playlists = None;
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dst.path().join("Album/Album.m3u8").exists());
+        assert!(dst.path().join("Album/01.mp3").exists());
+    }
+
+    #[test]
+    fn test_interrupted_playlist_write_is_cleaned_up() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir(src.path().join("Album")).unwrap();
+        fs::write(src.path().join("Album/01.mp3"), "one").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.playlists = Some(PlaylistConfig::default());
+        sync_folder(&task, false, false).unwrap();
+
+        // Left by a crash while the playlist was rewritten
+        let tmp = dst.path().join("Album/.Album.m3u8.plainsync-tmp");
+        fs::write(&tmp, "#EXTM3U\n").unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!tmp.exists());
+
+        // Also once the playlist is no longer planned, so its directory can go
+        fs::write(&tmp, "#EXTM3U\n").unwrap();
+        fs::remove_dir_all(src.path().join("Album")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dst.path().join("Album").exists());
+    }
+}
+
+#[cfg(test)]
+mod host_playlist_tests {
+    use super::*;
+    use plainsync::config::{SyncMode, TranscodeRule};
+    use plainsync::sync::playlists::relative_path;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path(Path::new("Playlists"), Path::new("Music/a.mp3")), Path::new("../Music/a.mp3"));
+        assert_eq!(relative_path(Path::new("Music/A"), Path::new("Music/A/b.mp3")), Path::new("b.mp3"));
+        assert_eq!(relative_path(Path::new(""), Path::new("Music/a.mp3")), Path::new("Music/a.mp3"));
+    }
+
+    #[test]
+    fn test_host_playlists_rewritten_to_device_paths() {
+        let (lib, lists, dev, data) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        fs::create_dir_all(lib.path().join("A/B")).unwrap();
+        fs::write(lib.path().join("A/B/01.flac"), "one").unwrap();
+        fs::write(lib.path().join("A/B/02.flac"), "two").unwrap();
+
+        // Library folder: transcoded, so device names differ from host names
+        let mut music = make_task(lib.path(), &dev.path().join("Music"), data.path());
+        music.folder.transcode = vec![TranscodeRule {
+            from: "flac".into(),
+            to: "opus".into(),
+            command: "cp {input} {output}".into(),
+        }];
+        sync_folder(&music, false, false).unwrap();
+
+        let lib_name = lib.path().file_name().unwrap().to_string_lossy();
+        fs::write(
+            lists.path().join("mix.m3u"),
+            format!(
+                "#EXTM3U\n#EXTINF:1,One\n{}/A/B/01.flac\n#EXTINF:9,Missing\n/elsewhere/x.flac\n../{}/A/B/02.flac\n",
+                lib.path().display(),
+                lib_name
+            ),
+        )
+        .unwrap();
+
+        let mut task = make_task(lists.path(), &dev.path().join("Playlists"), data.path());
+        task.index_path = data.path().join("playlists.bin");
+        task.folder.target = "Playlists".into();
+        task.mode = SyncMode::Playlists;
+        task.library = Some(Box::new(music));
+        sync_folder(&task, false, false).unwrap();
+
+        let out = dev.path().join("Playlists/mix.m3u8");
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "#EXTM3U\n#EXTINF:1,One\n../Music/A/B/01.opus\n../Music/A/B/02.opus\n"
+        );
+
+        // Unchanged: not rewritten
+        let written_at = fs::metadata(&out).unwrap().modified().unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(fs::metadata(&out).unwrap().modified().unwrap(), written_at);
+
+        // Host playlist removed: removed from the device
+        fs::remove_file(lists.path().join("mix.m3u")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_missing_library_is_an_error() {
+        let (lists, dev, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut task = make_task(lists.path(), dev.path(), data.path());
+        task.mode = SyncMode::Playlists;
+        assert!(sync_folder(&task, false, false).is_err());
+    }
+}
+
+#[cfg(test)]
+mod tag_rules_tests {
+    use super::*;
+    use plainsync::config::TagRules;
+    use plainsync::sync::run::pending_diff;
+
+    fn rules(yaml: &str) -> TagRules {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn library(src: &Path) {
+        fs::create_dir(src.join("Album")).unwrap();
+        fs::write(
+            src.join("Album/jazz.flac"),
+            flac(&["GENRE=Jazz", "ARTIST=Miles Davis", "DATE=1959", "RATING=5"]),
+        )
+        .unwrap();
+        fs::write(
+            src.join("Album/fusion.flac"),
+            flac(&[
+                "GENRE=Jazz Fusion",
+                "ARTIST=Weather Report",
+                "DATE=1977",
+                "RATING=60",
+            ]),
+        )
+        .unwrap();
+        fs::write(
+            src.join("Album/rock.flac"),
+            flac(&["GENRE=Rock", "ARTIST=Nickelback", "DATE=2001", "RATING=1"]),
+        )
+        .unwrap();
+        fs::write(src.join("Album/cover.jpg"), "cover").unwrap();
+    }
+
+    fn synced(dst: &Path) -> Vec<String> {
+        let mut out: Vec<String> = fs::read_dir(dst.join("Album"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_include_and_exclude_rules() {
+        let (src, dst, data) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.tag_rules = rules("include:\n  - genre: 'jazz*'\n  - rating: '>=4'\nexclude:\n  - artist: [Weather Report]\n");
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(synced(dst.path()), vec!["cover.jpg", "jazz.flac"]);
+    }
+
+    #[test]
+    fn test_numeric_range() {
+        let (src, dst, data) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.tag_rules = rules("include:\n  - year: 1950..1980\n");
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(
+            synced(dst.path()),
+            vec!["cover.jpg", "fusion.flac", "jazz.flac"]
+        );
+    }
+
+    #[test]
+    fn test_retagged_track_leaves_device() {
+        let (src, dst, data) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.tag_rules = rules("exclude:\n  - device: 'no'\n");
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Album/rock.flac").exists());
+
+        fs::write(
+            src.path().join("Album/rock.flac"),
+            flac(&["GENRE=Rock", "DEVICE=no"]),
+        )
+        .unwrap();
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dst.path().join("Album/rock.flac").exists());
+    }
+
+    #[test]
+    fn test_tags_come_from_cache() {
+        let (src, dst, data) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.tag_rules = rules("include:\n  - genre: Jazz\n");
+        sync_folder(&task, false, false).unwrap();
+
+        let mut idx = Index::load(&task.index_path).unwrap();
+        assert_eq!(idx.tag_cache.len(), 3);
+
+        // Unchanged files are not parsed again: edited cache entries win
+        let rock = idx
+            .tag_cache
+            .iter_mut()
+            .find(|c| c.path == "Album/rock.flac")
+            .unwrap();
+        rock.tags.0.insert("genre".into(), vec!["Jazz".into()]);
+        idx.save().unwrap();
+
+        let (diff, _) = pending_diff(&task).unwrap();
+        assert_eq!(diff.to_upload.len(), 1);
+        assert_eq!(diff.to_upload[0].path, "Album/rock.flac");
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+    use plainsync::core::tags::Tags;
+    use plainsync::sync::layout::render_layout;
+
+    const LAYOUT: &str = "{albumartist}/{year} - {album}/{track:02} {title}.{ext}";
+
+    fn library(src: &Path) {
+        fs::create_dir_all(src.join("misc/dl")).unwrap();
+        fs::write(
+            src.join("misc/dl/a.flac"),
+            flac(&["ARTIST=Miles Davis", "ALBUM=Kind of Blue", "DATE=1959-08-17", "TRACKNUMBER=1/5", "TITLE=So What"]),
+        ).unwrap();
+        fs::write(
+            src.join("misc/b.flac"),
+            flac(&["ARTIST=Miles Davis", "ALBUM=Kind of Blue", "DATE=1959", "TRACKNUMBER=2", "TITLE=Freddie Freeloader"]),
+        ).unwrap();
+        fs::write(src.join("misc/cover.jpg"), "cover").unwrap();
+    }
+
+    #[test]
+    fn test_render_layout() {
+        let mut tags = Tags::default();
+        tags.insert("artist", "AC/DC");
+        tags.insert("title", "What?");
+        tags.insert("tracknumber", "7/10");
+
+        assert_eq!(render_layout(LAYOUT, &tags, "x/song.mp3", "mp3"), "AC_DC/Unknown - Unknown/07 What_.mp3");
+        assert_eq!(render_layout("{album}/../{filename}.{ext}", &tags, "x/song.mp3", "mp3"), "Unknown/song.mp3");
+    }
+
+    #[test]
+    fn test_tracks_placed_from_tags() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.layout = Some(LAYOUT.into());
+        sync_folder(&task, false, false).unwrap();
+
+        let album = dst.path().join("Miles Davis/1959 - Kind of Blue");
+        assert!(album.join("01 So What.flac").exists());
+        assert!(album.join("02 Freddie Freeloader.flac").exists());
+        assert!(dst.path().join("misc/cover.jpg").exists(), "untagged files keep their path");
+        assert!(!dst.path().join("misc/dl").exists());
+
+        let idx = Index::load(&task.index_path).unwrap();
+        assert_eq!(idx.target_path("misc/dl/a.flac"), "Miles Davis/1959 - Kind of Blue/01 So What.flac");
+        assert_eq!(idx.placed_as("misc/cover.jpg"), None);
+    }
+
+    #[test]
+    fn test_tag_edit_moves_track() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.layout = Some(LAYOUT.into());
+        sync_folder(&task, false, false).unwrap();
+
+        fs::write(
+            src.path().join("misc/b.flac"),
+            flac(&["ARTIST=Miles Davis", "ALBUM=Sketches", "DATE=1960", "TRACKNUMBER=2", "TITLE=Freddie Freeloader"]),
+        ).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(dst.path().join("Miles Davis/1960 - Sketches/02 Freddie Freeloader.flac").exists());
+        assert!(!dst.path().join("Miles Davis/1959 - Kind of Blue/02 Freddie Freeloader.flac").exists());
+        assert!(dst.path().join("Miles Davis/1959 - Kind of Blue/01 So What.flac").exists());
+    }
+
+    #[test]
+    fn test_layout_change_moves_without_copy() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.layout = Some(LAYOUT.into());
+        sync_folder(&task, false, false).unwrap();
+
+        // A copy would overwrite the marker, a move keeps it
+        let old = dst.path().join("Miles Davis/1959 - Kind of Blue/01 So What.flac");
+        fs::write(&old, "marker").unwrap();
+
+        task.folder.layout = Some("{album}/{title}.{ext}".into());
+        sync_folder(&task, false, false).unwrap();
+
+        assert_eq!(fs::read_to_string(dst.path().join("Kind of Blue/So What.flac")).unwrap(), "marker");
+        assert!(!dst.path().join("Miles Davis").exists(), "emptied directories are pruned");
+
+        // Without a layout, tracks go back to their source path
+        task.folder.layout = None;
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(fs::read_to_string(dst.path().join("misc/dl/a.flac")).unwrap(), "marker");
+        assert!(Index::load(&task.index_path).unwrap().placed.is_empty());
+    }
+
+    #[test]
+    fn test_colliding_paths_are_numbered() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("a.flac"), flac(&["ALBUM=Live", "TITLE=Intro"])).unwrap();
+        fs::write(src.path().join("b.flac"), flac(&["ALBUM=Live", "TITLE=intro"])).unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.layout = Some("{album}/{title}.{ext}".into());
+        task.device.filesystem.portable_names = true;
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(dst.path().join("Live/Intro.flac").exists());
+        assert!(dst.path().join("Live/intro (2).flac").exists());
+    }
+}
+
+#[cfg(test)]
+mod rewrite_tests {
+    use super::*;
+    use plainsync::config::{RewritePattern, RewriteRule};
+    use plainsync::sync::rewrite::{flatten, rewrite_path};
+
+    fn rule(from: &str, to: &str) -> RewriteRule {
+        RewriteRule { from: RewritePattern::new(from).unwrap(), to: to.into() }
+    }
+
+    const CD_FOLDERS: &str = r"(?<artist>[^/]+)/(?<album>[^/]+)/(?i:CD)\d+/(?<file>[^/]+)";
+
+    #[test]
+    fn test_patterns() {
+        let rules = [rule(CD_FOLDERS, "$artist - $album/$file")];
+        assert_eq!(rewrite_path(&rules, "Miles/Kind of Blue/cd1/01.flac"), "Miles - Kind of Blue/01.flac");
+        // Patterns match the whole path, never a part of it
+        assert_eq!(rewrite_path(&rules, "Miles/Kind of Blue/01.flac"), "Miles/Kind of Blue/01.flac");
+        assert_eq!(rewrite_path(&rules, "Jazz/Miles/Kind of Blue/CD1/01.flac"), "Jazz/Miles/Kind of Blue/CD1/01.flac");
+
+        // Numbered groups, ${name} next to text, first matching rule wins
+        let rules = [
+            rule(r"(.*/)?(\d+) - (?<title>[^/]+)\.mp3", "${1}${title}.mp3"),
+            rule(r".*\.mp3", "other.mp3"),
+        ];
+        assert_eq!(rewrite_path(&rules, "a/b/01 - So What.mp3"), "a/b/So What.mp3");
+        assert_eq!(rewrite_path(&rules, "01 - So What.mp3"), "So What.mp3");
+        assert_eq!(rewrite_path(&rules, "a/x.mp3"), "other.mp3");
+
+        let parsed: RewriteRule = serde_yaml::from_str("from: '(.+)/CD\\d+/(.+)'\nto: '$1/$2'").unwrap();
+        assert_eq!(rewrite_path(&[parsed], "A/CD1/01.mp3"), "A/01.mp3");
+        assert!(serde_yaml::from_str::<RewriteRule>("from: '(unclosed'\nto: x").is_err());
+        assert!(RewritePattern::new("(unclosed").is_err());
+        assert_eq!(RewritePattern::new("a|b").unwrap().to_string(), "a|b");
+        assert_eq!(rewrite_path(&[rule("a|b", "c")], "ab"), "ab");
+    }
+
+    #[test]
+    fn test_rewrite_and_flatten() {
+        let rules = [rule(CD_FOLDERS, "$artist - $album/$file")];
+        assert_eq!(rewrite_path(&rules, "A/B/CD2/01.mp3"), "A - B/01.mp3");
+        assert_eq!(rewrite_path(&rules, "A/B/01.mp3"), "A/B/01.mp3");
+
+        assert_eq!(flatten("A/B/CD1/01.mp3", 1), "A - B - CD1/01.mp3");
+        assert_eq!(flatten("A/B/CD1/01.mp3", 2), "A/B - CD1/01.mp3");
+        assert_eq!(flatten("A/B/01.mp3", 0), "A - B - 01.mp3");
+        assert_eq!(flatten("A/01.mp3", 2), "A/01.mp3");
+    }
+
+    #[test]
+    fn test_sync_rewrites_and_records_paths() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album/CD1")).unwrap();
+        fs::create_dir_all(src.path().join("Artist/Other/Live/Set")).unwrap();
+        fs::write(src.path().join("Artist/Album/CD1/01.mp3"), "one").unwrap();
+        fs::write(src.path().join("Artist/Other/Live/Set/02.mp3"), "two").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.rewrite = vec![rule(CD_FOLDERS, "$artist - $album/$file")];
+        task.folder.max_depth = Some(2);
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(dst.path().join("Artist - Album/01.mp3").exists());
+        assert!(dst.path().join("Artist/Other - Live - Set/02.mp3").exists());
+
+        let idx = Index::load(&task.index_path).unwrap();
+        assert_eq!(idx.placed_as("Artist/Album/CD1/01.mp3"), Some("Artist - Album/01.mp3"));
+
+        // Dropping the rules moves files back, the source file is deleted
+        task.folder.rewrite.clear();
+        task.folder.max_depth = None;
+        fs::remove_file(src.path().join("Artist/Other/Live/Set/02.mp3")).unwrap();
+        sync_folder(&task, false, false).unwrap();
+
+        assert!(dst.path().join("Artist/Album/CD1/01.mp3").exists());
+        assert!(!dst.path().join("Artist - Album").exists());
+        assert!(!dst.path().join("Artist/Other - Live - Set").exists());
+    }
+
+    #[test]
+    fn test_collisions_fail_before_writing() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Album/CD1")).unwrap();
+        fs::create_dir_all(src.path().join("Album/CD2")).unwrap();
+        fs::write(src.path().join("Album/CD1/01.mp3"), "one").unwrap();
+        fs::write(src.path().join("Album/CD2/01.mp3"), "two").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.rewrite = vec![rule(r"([^/]+)/[^/]+/([^/]+)", "$1/$2")];
+
+        let err = sync_folder(&task, false, false).unwrap_err();
+        assert!(format!("{:#}", err).contains("Path collision"));
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_case_variants_on_case_sensitive_target() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("Track.mp3"), "upper").unwrap();
+        fs::write(src.path().join("track.mp3"), "lower").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+        assert_eq!(fs::read_to_string(dst.path().join("Track.mp3")).unwrap(), "upper");
+        assert_eq!(fs::read_to_string(dst.path().join("track.mp3")).unwrap(), "lower");
+
+        // A FAT device ignores case for the paths rewrite rules produce
+        fs::create_dir(src.path().join("Other")).unwrap();
+        fs::write(src.path().join("Other/TRACK.mp3"), "other").unwrap();
+        task.device.filesystem.portable_names = true;
+        task.folder.rewrite = vec![rule("Other/(.+)", "$1")];
+        let err = sync_folder(&task, false, false).unwrap_err();
+        assert!(format!("{:#}", err).contains("Path collision"));
+    }
+}
+
+#[cfg(test)]
+mod profile_tests {
+    use super::*;
+    use plainsync::config::{AppConfig, PlaylistPaths};
+
+    fn config(devices: &str) -> anyhow::Result<AppConfig> {
+        Ok(serde_yaml::from_str(&format!("watch:\n  enabled: false\n  debounce_ms: 100\ndevices:\n{}", devices))?)
+    }
+
+    #[test]
+    fn test_profile_merged_under_device() {
+        let conf = config(
+            r#"
+  - name: Y1
+    label: null
+    profile: rockbox
+    mount: /mnt/player
+    filesystem:
+      max_name: 64
+    folders:
+      - source: ~/Music
+        target: Music
+        enabled: true
+        playlists:
+      - source: ~/Books
+        target: Books
+        enabled: true
+        playlists:
+          paths: relative
+"#,
+        )
+        .unwrap();
+
+        let dev = &conf.devices[0];
+        assert_eq!(dev.mount, "/mnt/player");
+        assert!(dev.mountinfo);
+        assert!(dev.filesystem.portable_names);
+        assert_eq!(dev.filesystem.max_name, Some(64));
+        assert_eq!(dev.filesystem.max_path, Some(260));
+        assert!(dev.formats.as_ref().unwrap().contains(&"opus".to_string()));
+        assert!(dev.formats_from_profile);
+
+        assert_eq!(dev.folders[0].playlists.as_ref().unwrap().paths, PlaylistPaths::Absolute);
+        assert_eq!(dev.folders[1].playlists.as_ref().unwrap().paths, PlaylistPaths::Relative);
+    }
+
+    #[test]
+    fn test_explicit_formats_are_not_from_profile() {
+        let conf = config("  - name: X\n    profile: y1\n    formats: [mp3]\n    folders: []\n").unwrap();
+        assert_eq!(conf.devices[0].formats, Some(vec!["mp3".to_string()]));
+        assert!(!conf.devices[0].formats_from_profile);
+
+        let conf = config("  - name: X\n    profile: y1\n    folders: []\n").unwrap();
+        assert!(conf.devices[0].formats.as_ref().unwrap().contains(&"jpeg".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let err = config("  - name: X\n    profile: walkman\n    folders: []\n").unwrap_err();
+        assert!(err.to_string().contains("Unknown device profile"));
+    }
+
+    #[test]
+    fn test_formats_and_filesystem_rules() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("live: part 1.mp3"), "mp3").unwrap();
+        fs::write(src.path().join("a very long track name.mp3"), "mp3").unwrap();
+        fs::write(src.path().join("lossless.flac"), "flac").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.formats = Some(vec!["mp3".into()]);
+        task.device.filesystem.portable_names = true;
+        task.device.filesystem.max_name = Some(12);
+        sync_folder(&task, false, false).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(dst.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a very l.mp3", "live_ pa.mp3"]);
+    }
+
+    #[test]
+    fn test_path_limit_stops_sync() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("Artist/Album")).unwrap();
+        fs::write(src.path().join("Artist/Album/01 Track.mp3"), "mp3").unwrap();
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.filesystem.max_path = Some(20);
+
+        let err = sync_folder(&task, false, false).unwrap_err();
+        assert!(format!("{:#}", err).contains("Path too long"));
+        assert_eq!(fs::read_dir(dst.path()).unwrap().count(), 0);
+    }
+}
+
+#[cfg(test)]
+mod formats_tests {
+    use super::*;
+    use plainsync::sync::run::pending_diff;
+
+    fn library(src: &Path) {
+        fs::create_dir(src.join("Album")).unwrap();
+        fs::write(src.join("Album/01.flac"), "flac").unwrap();
+        fs::write(src.join("Album/02.flac"), "flac").unwrap();
+        fs::write(src.join("Album/03.mp3"), "mp3").unwrap();
+        fs::write(src.join("Album/cover.jpg"), "jpg").unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_files_are_reported_not_synced() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.formats = Some(vec!["mp3".into(), ".JPG".into()]);
+
+        let (diff, stats) = pending_diff(&task).unwrap();
+        assert_eq!(stats.upload_count, 2);
+        assert_eq!(diff.unsupported.len(), 2);
+        assert_eq!(plainsync::sync::formats::summarize(&diff.unsupported), "flac: 2");
+
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Album/03.mp3").exists());
+        assert!(dst.path().join("Album/cover.jpg").exists());
+        assert!(!dst.path().join("Album/01.flac").exists());
+    }
+
+    #[test]
+    fn test_transcoded_files_checked_by_output() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.device.formats = Some(vec!["mp3".into()]);
+        task.folder.transcode = vec![plainsync::config::TranscodeRule {
+            from: "flac".into(),
+            to: "mp3".into(),
+            command: "cp {input} {output}".into(),
+        }];
+
+        let (diff, _) = pending_diff(&task).unwrap();
+        let skipped: Vec<&str> = diff.unsupported.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(skipped, vec!["Album/cover.jpg"]);
+    }
+
+    #[test]
+    fn test_newly_unsupported_files_leave_device() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Album/01.flac").exists());
+
+        task.device.formats = Some(vec!["mp3".into()]);
+        sync_folder(&task, false, false).unwrap();
+        assert!(!dst.path().join("Album/01.flac").exists());
+        assert!(dst.path().join("Album/03.mp3").exists());
+    }
+
+    #[test]
+    fn test_profile_formats_keep_synced_files() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        library(src.path());
+
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        sync_folder(&task, false, false).unwrap();
+
+        // A list the user never wrote does not delete what is already there
+        task.device.formats = Some(vec!["mp3".into()]);
+        task.device.formats_from_profile = true;
+        fs::write(src.path().join("Album/new.flac"), "new").unwrap();
+
+        let (diff, _) = pending_diff(&task).unwrap();
+        assert!(diff.to_delete.is_empty());
+        assert_eq!(diff.kept.len(), 3);
+        assert_eq!(diff.unsupported.len(), 1);
+
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("Album/01.flac").exists());
+        assert!(dst.path().join("Album/cover.jpg").exists());
+        assert!(!dst.path().join("Album/new.flac").exists());
+    }
+}
+
+#[cfg(test)]
+mod stop_tests {
+    use super::*;
+    use plainsync::sync::operations::Progress;
+    use plainsync::sync::run::sync_folder_with;
+    use plainsync::utils::fs::StopSignal;
+
+    #[test]
+    fn test_stopped_sync_copies_nothing_more_and_resumes() {
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::write(src.path().join("a.mp3"), "a").unwrap();
+        fs::write(src.path().join("b.mp3"), "b").unwrap();
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        let hooks = data.path().join("hooks");
+        task.folder.hooks.on_error = Some(format!("echo on_error >> '{}'", hooks.display()));
+        task.folder.hooks.post_sync = Some(format!("echo post_sync >> '{}'", hooks.display()));
+
+        // Stopping is not a failure, and not a finished sync either
+        let stop = StopSignal::new();
+        stop.stop();
+        assert!(sync_folder_with(&task, false, &Progress::Quiet, &stop).unwrap().is_none());
+        assert!(!hooks.exists());
+        assert!(!dst.path().join("a.mp3").exists());
+        assert!(Index::load(&task.index_path).unwrap().files.is_empty());
+
+        sync_folder(&task, false, false).unwrap();
+        assert!(dst.path().join("a.mp3").exists());
+        assert!(dst.path().join("b.mp3").exists());
+        assert_eq!(fs::read_to_string(&hooks).unwrap(), "post_sync\n");
+    }
+
+    #[test]
+    fn test_failed_upload_keeps_created_dirs_managed() {
+        use plainsync::config::{RewritePattern, RewriteRule};
+        use plainsync::core::journal::Journal;
+
+        let (src, dst, data) = (TempDir::new().unwrap(), TempDir::new().unwrap(), TempDir::new().unwrap());
+        fs::create_dir_all(src.path().join("a/b.mp3x")).unwrap();
+        fs::write(src.path().join("a/b.mp3"), "b").unwrap();
+        fs::write(src.path().join("a/b.mp3x/c.mp3"), "c").unwrap();
+
+        // a/b.mp3 is wanted as both a file and a directory: the second copy fails
+        let mut task = make_task(src.path(), dst.path(), data.path());
+        task.folder.rewrite = vec![RewriteRule { from: RewritePattern::new(r"a/b\.mp3x/(.+)").unwrap(), to: "a/b.mp3/$1".into() }];
+        assert!(sync_folder(&task, false, false).is_err());
+
+        let mut idx = Index::load(&task.index_path).unwrap();
+        assert!(idx.dirs.is_empty());
+        Journal::for_index(&task.index_path).replay(&mut idx).unwrap();
+        assert_eq!(idx.dirs, vec!["a".to_string(), "a/b.mp3".to_string()]);
+    }
+}
@@ -3,7 +3,7 @@
 
 //! Unit tests for utility modules
 
-use plainsync::utils::{slug, human, paths};
+use plainsync::utils::{slug, human, paths, throttle, heartbeat, fs as ufs};
 
 #[cfg(test)]
 mod slug_tests {
@@ -209,3 +209,96 @@ mod paths_tests {
         assert!(nested_path.exists(), "All nested directories should be created");
     }
 }
+
+#[cfg(test)]
+mod throttle_tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_unlimited_throttle_does_not_wait() {
+        let mut t = throttle::Throttle::unlimited();
+        assert!(!t.is_limited());
+
+        let start = Instant::now();
+        t.consume(1024 * 1024 * 1024);
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_zero_limit_means_unlimited() {
+        let t = throttle::Throttle::new(Some(0));
+        assert!(!t.is_limited(), "0 bytes/s should disable throttling");
+    }
+
+    #[test]
+    fn test_limited_throttle_paces_writes() {
+        let mut t = throttle::Throttle::new(Some(10_000));
+        assert!(t.is_limited());
+
+        let start = Instant::now();
+        t.consume(2_000);
+        assert!(start.elapsed() >= Duration::from_millis(150), "2 KB at 10 KB/s should take ~200ms");
+    }
+
+    #[test]
+    fn test_throttle_window_starts_at_first_write() {
+        let mut t = throttle::Throttle::new(Some(10_000));
+        std::thread::sleep(Duration::from_millis(250));
+
+        let start = Instant::now();
+        t.consume(2_000);
+        assert!(start.elapsed() >= Duration::from_millis(150), "idle time before the first write is no burst credit");
+    }
+
+    #[test]
+    fn test_throttled_copy_preserves_content() {
+        use tempfile::TempDir;
+        use std::path::PathBuf;
+
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let data = vec![42u8; 200_000];
+        std::fs::write(src.path().join("song.flac"), &data).unwrap();
+
+        let mut opts = ufs::CopyOptions::new(Some(100 * 1024 * 1024), Default::default());
+        ufs::copy_one(src.path(), dst.path(), &PathBuf::from("song.flac"), &mut opts).unwrap();
+
+        assert_eq!(std::fs::read(dst.path().join("song.flac")).unwrap(), data);
+    }
+}
+
+#[cfg(test)]
+mod heartbeat_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_beat_resets_age() {
+        let hb = heartbeat::Heartbeat::new();
+        let shared = hb.clone();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(hb.age() >= Duration::from_millis(30));
+
+        shared.beat();
+        assert!(hb.age() < Duration::from_millis(30), "clones share the same heartbeat");
+    }
+
+    #[test]
+    fn test_pulse_beats_the_heartbeat_of_its_thread() {
+        let hb = heartbeat::Heartbeat::new();
+        std::thread::sleep(Duration::from_millis(30));
+
+        // Outside `around`, and on other threads, pulses go nowhere
+        heartbeat::pulse();
+        std::thread::spawn(heartbeat::pulse).join().unwrap();
+        assert!(hb.age() >= Duration::from_millis(30));
+
+        hb.around(heartbeat::pulse);
+        assert!(hb.age() < Duration::from_millis(30));
+
+        std::thread::sleep(Duration::from_millis(30));
+        heartbeat::pulse();
+        assert!(hb.age() >= Duration::from_millis(30), "only pulses within `around` count");
+    }
+}
@@ -134,3 +134,167 @@ mod validation_tests {
         assert!(result.is_ok());
     }
 }
+
+#[cfg(test)]
+mod temp_files_tests {
+    use super::*;
+    use plainsync::core::journal::{Journal, JournalEntry};
+    use plainsync::utils::fs::{copy_one, remove_temp_files, temp_path, CopyOptions};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_interrupted_copy_temp_file_removed() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("target");
+        let album = target.join("Artist").join("Album");
+        fs::create_dir_all(&album).unwrap();
+
+        let song = album.join("song.flac");
+        fs::write(temp_path(&song), "truncated").unwrap();
+        fs::write(album.join(".hidden"), "user file").unwrap();
+
+        // The interrupted run recorded the copy it started
+        let mut journal = Journal::for_index(&temp.path().join("index.bin"));
+        journal.record(&JournalEntry::Copying { target: "Artist/Album/song.flac".into() }).unwrap();
+
+        let removed = remove_temp_files(&target, &journal.in_progress().unwrap()).unwrap();
+
+        assert_eq!(removed, 1);
+        assert!(!temp_path(&song).exists(), "Stale temp file should be removed");
+        assert!(album.join(".hidden").exists(), "Unrelated dotfiles must be kept");
+    }
+
+    #[test]
+    fn test_copy_one_leaves_no_temp_file() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(src.path().join("song.flac"), "audio").unwrap();
+
+        let rel = PathBuf::from("song.flac");
+        copy_one(src.path(), dst.path(), &rel, &mut CopyOptions::default()).unwrap();
+
+        let copied = dst.path().join("song.flac");
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "audio");
+        assert!(!temp_path(&copied).exists());
+    }
+
+    #[test]
+    fn test_copy_one_replaces_existing_file() {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        fs::write(src.path().join("song.flac"), "new version").unwrap();
+        fs::write(dst.path().join("song.flac"), "old").unwrap();
+
+        let rel = PathBuf::from("song.flac");
+        copy_one(src.path(), dst.path(), &rel, &mut CopyOptions::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(dst.path().join("song.flac")).unwrap(), "new version");
+    }
+}
+
+#[cfg(test)]
+mod preserve_tests {
+    use super::*;
+    use plainsync::config::PreserveConfig;
+    use plainsync::utils::fs::{copy_one, CopyOptions};
+    use std::fs::FileTimes;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    fn copy_with(policy: PreserveConfig) -> (TempDir, TempDir, SystemTime) {
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let path = src.path().join("song.flac");
+        fs::write(&path, "audio").unwrap();
+
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(&path).unwrap()
+            .set_times(FileTimes::new().set_modified(old)).unwrap();
+
+        let mut opts = CopyOptions::new(None, policy);
+        copy_one(src.path(), dst.path(), &PathBuf::from("song.flac"), &mut opts).unwrap();
+        (src, dst, old)
+    }
+
+    #[test]
+    fn test_default_policy_preserves_mtime() {
+        let (_src, dst, old) = copy_with(PreserveConfig::default());
+        let copied = fs::metadata(dst.path().join("song.flac")).unwrap();
+        assert_eq!(copied.modified().unwrap(), old);
+    }
+
+    #[test]
+    fn test_mtime_not_preserved_when_disabled() {
+        let policy = PreserveConfig { mtime: false, ..Default::default() };
+        let (_src, dst, old) = copy_with(policy);
+        let copied = fs::metadata(dst.path().join("song.flac")).unwrap();
+        assert_ne!(copied.modified().unwrap(), old);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_preserved_when_enabled() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let src = TempDir::new().unwrap();
+        let dst = TempDir::new().unwrap();
+        let path = src.path().join("script.sh");
+        fs::write(&path, "#!/bin/sh").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        let policy = PreserveConfig { permissions: true, ..Default::default() };
+        let mut opts = CopyOptions::new(None, policy);
+        copy_one(src.path(), dst.path(), &PathBuf::from("script.sh"), &mut opts).unwrap();
+
+        let mode = fs::metadata(dst.path().join("script.sh")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_not_copied_when_disabled() {
+        use std::os::unix::fs::PermissionsExt;
+
+        for limit in [None, Some(1_000_000)] {
+            let src = TempDir::new().unwrap();
+            let dst = TempDir::new().unwrap();
+            let path = src.path().join("script.sh");
+            fs::write(&path, "#!/bin/sh").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+            let policy = PreserveConfig { permissions: false, ..Default::default() };
+            let mut opts = CopyOptions::new(limit, policy);
+            copy_one(src.path(), dst.path(), &PathBuf::from("script.sh"), &mut opts).unwrap();
+
+            let mode = fs::metadata(dst.path().join("script.sh")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0, "source mode leaked ({:o}, limit {:?})", mode & 0o777, limit);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_only_source() {
+        use std::os::unix::fs::PermissionsExt;
+
+        for permissions in [true, false] {
+            let src = TempDir::new().unwrap();
+            let dst = TempDir::new().unwrap();
+            let path = src.path().join("song.flac");
+            fs::write(&path, "audio").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+            let policy = PreserveConfig { permissions, xattrs: true, ..Default::default() };
+            let mut opts = CopyOptions::new(None, policy);
+            copy_one(src.path(), dst.path(), &PathBuf::from("song.flac"), &mut opts).unwrap();
+
+            let copied = dst.path().join("song.flac");
+            assert_eq!(fs::read_to_string(&copied).unwrap(), "audio");
+            let mode = fs::metadata(&copied).unwrap().permissions().mode() & 0o777;
+            if permissions {
+                assert_eq!(mode, 0o444);
+            } else {
+                assert_ne!(mode & 0o200, 0, "copy should stay writable ({:o})", mode);
+            }
+        }
+    }
+}